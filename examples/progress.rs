@@ -0,0 +1,39 @@
+use std::{io, thread, time::Duration};
+use termular::printer::{Color, Styled};
+use termular::screen::Viewport;
+use termular::{Mode, Term};
+
+fn main() -> io::Result<()> {
+    let (stdout, stderr) = (io::stdout(), io::stderr());
+
+    let mut term = Term::open(stdout.lock(), stderr.lock())?;
+    term.flow()
+        .set_mode(Mode::Raw)
+        .screen()
+        .set_viewport(Viewport::Inline { height: 2 })
+        .cursor()
+        .hide()
+        .flush()?;
+
+    let origin = term.viewport_origin();
+    let width = usize::from(term.viewport_size()?[0].min(42).saturating_sub(2));
+
+    for done in 0..=width {
+        term.screen()
+            .clear_viewport()
+            .printer()
+            .print(format!("Downloading... {}%", done * 100 / width))
+            .cursor()
+            .set_position(origin + [0, 1])
+            .printer()
+            .print("[")
+            .print("#".repeat(done).with_foreground(Color::Green))
+            .print(" ".repeat(width - done))
+            .print("]")
+            .flush()?;
+
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    Ok(())
+}
@@ -51,7 +51,7 @@ fn main() -> io::Result<()> {
                 .set_position(center - [6, 4])
                 .erase_line()
                 .printer()
-                .debug(&buf)
+                .debug(buf)
                 .flush()
                 .unwrap();
         }
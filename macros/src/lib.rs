@@ -0,0 +1,108 @@
+use proc_macro::{Delimiter, Group, Ident, Literal, Punct, Spacing, TokenStream, TokenTree};
+
+// Expands `format_escaped!(Wrapper; "format", args...)` to
+// `format!("format", args..., name = Wrapper(&name)...)`, so that the
+// arguments captured inline in the format string are wrapped as well.
+#[proc_macro]
+pub fn format_escaped(input: TokenStream) -> TokenStream {
+    let mut tokens = input.into_iter().map(flatten);
+
+    let wrapper: Vec<_> = tokens
+        .by_ref()
+        .take_while(|t| !matches!(t, TokenTree::Punct(p) if p.as_char() == ';'))
+        .collect();
+
+    let Some(TokenTree::Literal(format)) = tokens.next() else {
+        return compile_error("expected a format string literal");
+    };
+    let rest: Vec<_> = tokens.collect();
+
+    let mut args = TokenStream::new();
+    args.extend([TokenTree::Literal(format.clone())]);
+    args.extend(rest);
+
+    for name in captures(&format.to_string()) {
+        let ident = Ident::new(&name, format.span());
+        let reference = [
+            TokenTree::Punct(Punct::new('&', Spacing::Alone)),
+            TokenTree::Ident(ident.clone()),
+        ];
+
+        args.extend([
+            TokenTree::Punct(Punct::new(',', Spacing::Alone)),
+            TokenTree::Ident(ident),
+            TokenTree::Punct(Punct::new('=', Spacing::Alone)),
+        ]);
+        args.extend(wrapper.iter().cloned());
+        args.extend([TokenTree::Group(Group::new(
+            Delimiter::Parenthesis,
+            reference.into_iter().collect(),
+        ))]);
+    }
+
+    let mut out: TokenStream = "::std::format!".parse().unwrap();
+    out.extend([TokenTree::Group(Group::new(Delimiter::Parenthesis, args))]);
+    out
+}
+
+// Lists the names captured inline by the format string, as written in the
+// source, e.g. `path` in `"{path}"` or `"{path:>8}"`, but not in `"{{path}}"`.
+fn captures(source: &str) -> Vec<String> {
+    let raw = source.starts_with('r');
+    let mut chars = source.chars().peekable();
+    let mut names = Vec::new();
+
+    while let Some(c) = chars.next() {
+        match c {
+            // Skips the escapes, `\u{..}` included.
+            '\\' if !raw => {
+                if chars.next() == Some('u') {
+                    chars.by_ref().find(|&c| c == '}');
+                }
+            }
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+            }
+            '{' => {
+                let mut name = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c == '_' || c.is_alphanumeric() {
+                        name.push(c);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+
+                let starts_ident = name.starts_with(|c: char| c == '_' || c.is_alphabetic());
+                if starts_ident && name != "_" && !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    names
+}
+
+// Unwraps the invisible groups around the `macro_rules` fragments.
+fn flatten(token: TokenTree) -> TokenTree {
+    match token {
+        TokenTree::Group(g) if g.delimiter() == Delimiter::None => {
+            let mut inner = g.stream().into_iter();
+            match (inner.next(), inner.next()) {
+                (Some(t), None) => flatten(t),
+                _ => TokenTree::Group(g),
+            }
+        }
+        t => t,
+    }
+}
+
+fn compile_error(msg: &str) -> TokenStream {
+    let mut out: TokenStream = "::std::compile_error!".parse().unwrap();
+    let msg = TokenStream::from(TokenTree::Literal(Literal::string(msg)));
+    out.extend([TokenTree::Group(Group::new(Delimiter::Parenthesis, msg))]);
+    out
+}
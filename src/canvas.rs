@@ -0,0 +1,444 @@
+use crate::printer::{Color, Style, Styled};
+use crate::vector::Vector2;
+
+use std::fmt::{self, Display, Formatter};
+
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Marker {
+    #[default]
+    HalfBlock,
+    Quadrant,
+    Sextant,
+    Braille,
+}
+
+impl Marker {
+    // Number of dots per cell on each axis.
+    #[must_use]
+    pub fn resolution(self) -> Vector2<usize> {
+        match self {
+            Self::HalfBlock => [1, 2],
+            Self::Quadrant => [2, 2],
+            Self::Sextant => [2, 3],
+            Self::Braille => [2, 4],
+        }
+        .into()
+    }
+
+    // `bits` has a bit set for each lit dot, row by row, left to right.
+    #[must_use]
+    fn symbol(self, bits: u8) -> char {
+        match self {
+            Self::HalfBlock => [' ', '▀', '▄', '█'][usize::from(bits)],
+            Self::Quadrant => QUADRANTS[usize::from(bits)],
+            Self::Sextant => match bits {
+                0 => ' ',
+                21 => '▌',
+                42 => '▐',
+                63 => '█',
+                // From: (https://www.unicode.org/charts/PDF/U1FB00.pdf)
+                // sextants are ordered by their bits, skipping the four
+                // patterns that were already defined in the block elements.
+                n => {
+                    let skipped = u32::from(n > 21) + u32::from(n > 42);
+                    char::from_u32(0x1FB00 + u32::from(n) - 1 - skipped).unwrap_or(' ')
+                }
+            },
+            // From: (https://en.wikipedia.org/wiki/Braille_Patterns#Identifying,_naming_and_ordering)
+            Self::Braille => {
+                let dots = BRAILLE
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| bits & (1 << i) != 0)
+                    .fold(0, |acc, (_, dot)| acc | dot);
+                char::from_u32(0x2800 + dots).unwrap_or(' ')
+            }
+        }
+    }
+}
+
+const QUADRANTS: [char; 16] = [
+    ' ', '▘', '▝', '▀', '▖', '▌', '▞', '▛', '▗', '▚', '▐', '▜', '▄', '▙', '▟', '█',
+];
+
+const BRAILLE: [u32; 8] = [0x01, 0x08, 0x02, 0x10, 0x04, 0x20, 0x40, 0x80];
+
+// A grid of dots rendered through unicode block or braille characters,
+// dot coordinates grow rightwards and downwards starting from `[0, 0]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Canvas {
+    size: Vector2<u16>,
+    marker: Marker,
+    dots: Vec<Option<Color>>,
+}
+
+impl Canvas {
+    #[must_use]
+    pub fn new(size: impl Into<Vector2<u16>>, marker: Marker) -> Self {
+        let size = size.into();
+        let [width, height] = (resolution(size, marker)).into_inner();
+
+        Self {
+            size,
+            marker,
+            dots: vec![None; width * height],
+        }
+    }
+
+    // Size in cells.
+    #[must_use]
+    pub fn size(&self) -> Vector2<u16> {
+        self.size
+    }
+
+    #[must_use]
+    pub fn marker(&self) -> Marker {
+        self.marker
+    }
+
+    // Size in dots.
+    #[must_use]
+    pub fn resolution(&self) -> Vector2<usize> {
+        resolution(self.size, self.marker)
+    }
+
+    pub fn clear(&mut self) {
+        self.dots.fill(None);
+    }
+
+    #[must_use]
+    pub fn get(&self, dot: impl Into<Vector2<f64>>) -> Option<Color> {
+        self.index(dot.into()).and_then(|i| self.dots[i])
+    }
+
+    // Dots outside the canvas are ignored.
+    pub fn set(&mut self, dot: impl Into<Vector2<f64>>, color: Color) {
+        if let Some(i) = self.index(dot.into()) {
+            self.dots[i] = Some(color);
+        }
+    }
+
+    pub fn unset(&mut self, dot: impl Into<Vector2<f64>>) {
+        if let Some(i) = self.index(dot.into()) {
+            self.dots[i] = None;
+        }
+    }
+
+    // Only the steps of the segment within the canvas are visited,
+    // segments with non-finite ends are ignored.
+    pub fn line(
+        &mut self,
+        from: impl Into<Vector2<f64>>,
+        to: impl Into<Vector2<f64>>,
+        color: Color,
+    ) {
+        let (from, to) = (from.into(), to.into());
+        let Some((enter, exit)) = self.clip(from, to) else {
+            return;
+        };
+        let [dx, dy] = (to - from).into_inner();
+        let steps = dx.abs().max(dy.abs()).ceil().max(1.0);
+        let (first, last) = ((enter * steps).floor(), (exit * steps).ceil());
+
+        for step in 0..=(last - first) as usize {
+            let t = (first + step as f64) / steps;
+            self.set(from + (to - from) * t, color);
+        }
+    }
+
+    // `from` and `to` are opposite corners of the rectangle.
+    pub fn rectangle(
+        &mut self,
+        from: impl Into<Vector2<f64>>,
+        to: impl Into<Vector2<f64>>,
+        color: Color,
+    ) {
+        let ([x0, y0], [x1, y1]) = (from.into().into_inner(), to.into().into_inner());
+
+        self.line([x0, y0], [x1, y0], color);
+        self.line([x1, y0], [x1, y1], color);
+        self.line([x1, y1], [x0, y1], color);
+        self.line([x0, y1], [x0, y0], color);
+    }
+
+    pub fn fill_rectangle(
+        &mut self,
+        from: impl Into<Vector2<f64>>,
+        to: impl Into<Vector2<f64>>,
+        color: Color,
+    ) {
+        let ([x0, y0], [x1, y1]) = (from.into().into_inner(), to.into().into_inner());
+        if ![x0, y0, x1, y1].iter().all(|n| n.is_finite()) {
+            return;
+        }
+
+        // Rows outside the canvas are skipped, `line` clips the columns.
+        let [_, height] = self.resolution().into_inner();
+        let top = y0.min(y1).round().max(0.0);
+        let bottom = y0.max(y1).round().min(height as f64 - 1.0);
+        if top > bottom {
+            return;
+        }
+
+        for y in top as usize..=bottom as usize {
+            self.line([x0, y as f64], [x1, y as f64], color);
+        }
+    }
+
+    // The circumference is sampled once per column and once per row of
+    // the canvas, so that no gaps are left and dots outside the canvas
+    // are never visited.
+    pub fn circle(&mut self, center: impl Into<Vector2<f64>>, radius: f64, color: Color) {
+        let [cx, cy] = center.into().into_inner();
+        if ![cx, cy, radius].iter().all(|n| n.is_finite()) {
+            return;
+        }
+
+        let radius = radius.abs();
+        let [width, height] = self.resolution().into_inner();
+
+        for x in span(cx, radius, width) {
+            let dy = (radius * radius - (x as f64 - cx).powi(2)).max(0.0).sqrt();
+            self.set([x as f64, cy - dy], color);
+            self.set([x as f64, cy + dy], color);
+        }
+        for y in span(cy, radius, height) {
+            let dx = (radius * radius - (y as f64 - cy).powi(2)).max(0.0).sqrt();
+            self.set([cx - dx, y as f64], color);
+            self.set([cx + dx, y as f64], color);
+        }
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = Row<'_>> {
+        (0..self.size[1]).map(move |y| Row { canvas: self, y })
+    }
+
+    #[must_use]
+    fn index(&self, dot: Vector2<f64>) -> Option<usize> {
+        let [width, height] = self.resolution().into_inner();
+        let [x, y] = dot.into_inner().map(f64::round);
+
+        (x >= 0.0 && y >= 0.0 && (x as usize) < width && (y as usize) < height)
+            .then(|| y as usize * width + x as usize)
+    }
+
+    // From: (https://en.wikipedia.org/wiki/Liang%E2%80%93Barsky_algorithm)
+    // the range of the segment, from `0.0` at `from` to `1.0` at `to`,
+    // crossing the canvas with a margin of a dot.
+    #[must_use]
+    fn clip(&self, from: Vector2<f64>, to: Vector2<f64>) -> Option<(f64, f64)> {
+        let ([x0, y0], [x1, y1]) = (from.into_inner(), to.into_inner());
+        if ![x0, y0, x1, y1].iter().all(|n| n.is_finite()) {
+            return None;
+        }
+
+        let [width, height] = self.resolution().into_inner();
+        let [dx, dy] = [x1 - x0, y1 - y0];
+        let edges = [
+            (-dx, x0 + 1.0),
+            (dx, width as f64 - x0),
+            (-dy, y0 + 1.0),
+            (dy, height as f64 - y0),
+        ];
+
+        let (mut enter, mut exit) = (0.0_f64, 1.0_f64);
+        for (p, q) in edges {
+            if p == 0.0 {
+                if q < 0.0 {
+                    return None;
+                }
+            } else if p < 0.0 {
+                enter = enter.max(q / p);
+            } else {
+                exit = exit.min(q / p);
+            }
+        }
+
+        (enter <= exit).then_some((enter, exit))
+    }
+
+    // Each cell can have only one foreground color, the most used among
+    // the lit dots wins, except for half blocks where the background
+    // color is used for the lower dot.
+    #[must_use]
+    fn cell(&self, cell: Vector2<usize>) -> (char, Style) {
+        let [rx, ry] = self.marker.resolution().into_inner();
+        let [width, _] = self.resolution().into_inner();
+        let [cx, cy] = (cell * [rx, ry]).into_inner();
+
+        let dots = (0..ry)
+            .flat_map(|y| (0..rx).map(move |x| (cy + y) * width + cx + x))
+            .map(|i| self.dots[i])
+            .collect::<Vec<_>>();
+
+        if let (Marker::HalfBlock, [Some(top), Some(bottom)]) = (self.marker, &dots[..]) {
+            if top != bottom {
+                return ('▀', Style::foreground(*top).with_background(*bottom));
+            }
+        }
+
+        let bits = dots
+            .iter()
+            .enumerate()
+            .filter(|(_, dot)| dot.is_some())
+            .fold(0, |bits, (i, _)| bits | 1 << i);
+
+        let color = dots
+            .iter()
+            .flatten()
+            .max_by_key(|c| dots.iter().filter(|d| d.as_ref() == Some(c)).count())
+            .copied()
+            .unwrap_or_default();
+
+        (self.marker.symbol(bits), Style::foreground(color))
+    }
+}
+
+// Dots within `radius` from `center` along an axis of `len` dots.
+fn span(center: f64, radius: f64, len: usize) -> impl Iterator<Item = usize> {
+    let first = (center - radius).ceil().max(0.0);
+    let last = (center + radius).floor().min(len as f64 - 1.0);
+    (first <= last)
+        .then_some(first as usize..=last as usize)
+        .into_iter()
+        .flatten()
+}
+
+#[must_use]
+fn resolution(size: Vector2<u16>, marker: Marker) -> Vector2<usize> {
+    let [width, height] = size.into_inner().map(usize::from);
+    marker.resolution() * [width, height]
+}
+
+// A row of cells, consecutive cells sharing the same style are printed
+// as a single styled content.
+pub struct Row<'a> {
+    canvas: &'a Canvas,
+    y: u16,
+}
+
+impl<'a> Display for Row<'a> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let mut run = String::new();
+        let mut style = None;
+
+        for x in 0..self.canvas.size[0] {
+            let (symbol, cell) = self
+                .canvas
+                .cell([usize::from(x), usize::from(self.y)].into());
+            if style.is_some_and(|s| s != cell) {
+                write_run(f, &mut run, style.unwrap_or_default())?;
+            }
+            style = Some(cell);
+            run.push(symbol);
+        }
+
+        write_run(f, &mut run, style.unwrap_or_default())
+    }
+}
+
+fn write_run(f: &mut Formatter, run: &mut String, style: Style) -> fmt::Result {
+    let content = std::mem::take(run);
+    if style == Style::default() {
+        write!(f, "{}", content)
+    } else {
+        write!(f, "{}", content.with_style(style))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn markers() {
+        let symbols = |marker: Marker| {
+            let mut sut = Canvas::new([1, 1], marker);
+            sut.set([0.0, 0.0], Color::Default);
+            let first = sut.rows().next().unwrap().to_string();
+            sut.line([0.0, 0.0], [1.0, 3.0], Color::Default);
+            sut.line([1.0, 0.0], [0.0, 3.0], Color::Default);
+            let second = sut.rows().next().unwrap().to_string();
+            (first, second)
+        };
+
+        assert_eq!(symbols(Marker::HalfBlock), ("▀".into(), "█".into()));
+        assert_eq!(symbols(Marker::Quadrant), ("▘".into(), "█".into()));
+        assert_eq!(symbols(Marker::Sextant), ("🬀".into(), "█".into()));
+        assert_eq!(symbols(Marker::Braille), ("⠁".into(), "⣿".into()));
+
+        let mut sut = Canvas::new([2, 1], Marker::Sextant);
+        sut.line([0.0, 0.0], [0.0, 2.0], Color::Default);
+        sut.set([3.0, 1.0], Color::Default);
+        assert_eq!(sut.rows().next().unwrap().to_string(), "▌🬇");
+    }
+
+    #[test]
+    fn shapes() {
+        let mut sut = Canvas::new([5, 3], Marker::Braille);
+        assert_eq!(sut.resolution(), [10, 12].into());
+
+        sut.rectangle([0.0, 0.0], [9.0, 11.0], Color::Default);
+        assert!(sut.get([9.0, 11.0]).is_some());
+        assert!(sut.get([4.0, 0.0]).is_some());
+        assert!(sut.get([4.0, 5.0]).is_none());
+
+        sut.clear();
+        sut.circle([5.0, 6.0], 4.0, Color::Red);
+        assert_eq!(sut.get([9.0, 6.0]), Some(Color::Red));
+        assert_eq!(sut.get([5.0, 2.0]), Some(Color::Red));
+        assert_eq!(sut.get([5.0, 6.0]), None);
+
+        sut.set([-1.0, 20.0], Color::Red);
+        sut.fill_rectangle([0.0, 0.0], [9.0, 11.0], Color::Blue);
+        assert!(sut.dots.iter().all(|d| *d == Some(Color::Blue)));
+    }
+
+    #[test]
+    fn clipping() {
+        let mut sut = Canvas::new([5, 3], Marker::Braille);
+
+        sut.line([f64::NEG_INFINITY, 0.0], [5.0, 0.0], Color::Red);
+        sut.line([0.0, f64::NAN], [5.0, 0.0], Color::Red);
+        sut.fill_rectangle([0.0, 0.0], [f64::INFINITY, 1.0], Color::Red);
+        sut.circle([5.0, 6.0], f64::INFINITY, Color::Red);
+        assert!(sut.dots.iter().all(Option::is_none));
+
+        sut.line([-1e9, -1e9], [1e9, 1e9], Color::Red);
+        assert_eq!(sut.get([0.0, 0.0]), Some(Color::Red));
+        assert_eq!(sut.get([9.0, 9.0]), Some(Color::Red));
+        assert_eq!(sut.get([9.0, 10.0]), None);
+
+        sut.clear();
+        sut.line([-1e12, 3.0], [1e12, 3.0], Color::Red);
+        assert!((0..10).all(|x| sut.get([x as f64, 3.0]).is_some()));
+        sut.line([-5.0, -5.0], [-1e12, 20.0], Color::Blue);
+        assert_eq!(sut.dots.iter().flatten().count(), 10);
+
+        sut.fill_rectangle([-1e12, -1e12], [1e12, 1e12], Color::Blue);
+        assert!(sut.dots.iter().all(|d| *d == Some(Color::Blue)));
+
+        sut.clear();
+        sut.circle([5.0, 1e9 + 6.0], 1e9, Color::Red);
+        assert_eq!(sut.get([5.0, 6.0]), Some(Color::Red));
+        assert_eq!(sut.get([5.0, 7.0]), None);
+        sut.circle([5.0, 6.0], 1e9, Color::Red);
+        sut.circle([-1e9, -1e9], 4.0, Color::Red);
+        assert_eq!(sut.dots.iter().flatten().count(), 10);
+    }
+
+    #[test]
+    fn colors() {
+        let mut sut = Canvas::new([2, 1], Marker::HalfBlock);
+        sut.set([0.0, 0.0], Color::Red);
+        sut.set([0.0, 1.0], Color::Blue);
+        sut.set([1.0, 1.0], Color::Green);
+
+        let red_over_blue = "▀".with_foreground(Color::Red).with_background(Color::Blue);
+        let green = "▄".with_foreground(Color::Green);
+        assert_eq!(
+            sut.rows().next().unwrap().to_string(),
+            format!("{}{}", red_over_blue, green)
+        );
+    }
+}
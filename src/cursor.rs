@@ -1,70 +1,81 @@
 use crate::flow::Flow;
-use crate::nio::ReadNonblock;
 use crate::printer::Printer;
+use crate::restore;
 use crate::screen::Screen;
 use crate::vector::Vector2;
-use crate::{Mode, Term};
+use crate::Term;
 
-use std::io::{self, Write};
-use std::time::Duration;
+use std::io::{self, StderrLock, StdoutLock, Write};
 use std::{error, str};
 
-pub struct Cursor<'a: 'b, 'b>(pub(crate) io::Result<&'b mut Term<'a>>);
+pub struct Cursor<'a: 'b, 'b, W: Write = StdoutLock<'a>, E: Write = StderrLock<'a>>(
+    pub(crate) io::Result<&'b mut Term<'a, W, E>>,
+);
 
-impl<'a, 'b> Cursor<'a, 'b> {
+impl<'a, 'b, W: Write, E: Write> Cursor<'a, 'b, W, E> {
     #[must_use]
-    pub fn printer(self) -> Printer<'a, 'b> {
+    pub fn printer(self) -> Printer<'a, 'b, W, E> {
         Printer(self.0)
     }
 
     #[must_use]
-    pub fn screen(self) -> Screen<'a, 'b> {
+    pub fn screen(self) -> Screen<'a, 'b, W, E> {
         Screen(self.0)
     }
 
     #[must_use]
-    pub fn flow(self) -> Flow<'a, 'b> {
+    pub fn flow(self) -> Flow<'a, 'b, W, E> {
         Flow(self.0)
     }
 
     #[must_use]
     pub fn hide(self) -> Self {
-        self.chain(|t| write!(t.stdout_mut(), "\x1B[?25l"))
+        self.chain(|t| {
+            if t.tty {
+                restore::set_cursor_hidden(true);
+            }
+            t.write_capability("civis", &[], format_args!("\x1B[?25l"))
+        })
     }
 
     #[must_use]
     pub fn show(self) -> Self {
-        self.chain(|t| write!(t.stdout_mut(), "\x1B[?25h"))
+        self.chain(|t| {
+            if t.tty {
+                restore::set_cursor_hidden(false);
+            }
+            t.write_capability("cnorm", &[], format_args!("\x1B[?25h"))
+        })
     }
 
     #[must_use]
     pub fn save(self) -> Self {
-        self.chain(|t| write!(t.stdout_mut(), "\x1B[s"))
+        self.chain(|t| t.write_capability("sc", &[], format_args!("\x1B[s")))
     }
 
     #[must_use]
     pub fn restore(self) -> Self {
-        self.chain(|t| write!(t.stdout_mut(), "\x1B[u"))
+        self.chain(|t| t.write_capability("rc", &[], format_args!("\x1B[u")))
     }
 
     #[must_use]
     pub fn up(self, n: u16) -> Self {
-        self.chain(|t| write!(t.stdout_mut(), "\x1B[{}A", n))
+        self.chain(|t| t.write_capability("cuu", &[n.into()], format_args!("\x1B[{}A", n)))
     }
 
     #[must_use]
     pub fn down(self, n: u16) -> Self {
-        self.chain(|t| write!(t.stdout_mut(), "\x1B[{}B", n))
+        self.chain(|t| t.write_capability("cud", &[n.into()], format_args!("\x1B[{}B", n)))
     }
 
     #[must_use]
     pub fn right(self, n: u16) -> Self {
-        self.chain(|t| write!(t.stdout_mut(), "\x1B[{}C", n))
+        self.chain(|t| t.write_capability("cuf", &[n.into()], format_args!("\x1B[{}C", n)))
     }
 
     #[must_use]
     pub fn left(self, n: u16) -> Self {
-        self.chain(|t| write!(t.stdout_mut(), "\x1B[{}D", n))
+        self.chain(|t| t.write_capability("cub", &[n.into()], format_args!("\x1B[{}D", n)))
     }
 
     pub fn flush(self) -> io::Result<()> {
@@ -73,35 +84,19 @@ impl<'a, 'b> Cursor<'a, 'b> {
 
     #[must_use]
     pub fn set_position(self, pos: impl Into<Vector2<u16>>) -> Self {
-        let [x, y] = pos.into().into_inner();
-        self.chain(|t| {
-            write!(
-                t.stdout_mut(),
-                "\x1B[{};{}H",
-                y.saturating_add(1),
-                x.saturating_add(1)
-            )
-        })
+        let pos = pos.into();
+        self.chain(|t| write_position(t, pos))
     }
 
     pub fn position(self) -> io::Result<Vector2<u16>> {
-        let term = self.0?;
-
-        term.stdout_mut().flush()?;
-        crate::with_mode(Mode::Raw, || {
-            let mut buf = Vec::new();
-
-            term.stderr_mut().write_all(b"\x1B[6n")?;
-            term.stdin_mut()
-                .read_timeout_until(b'R', &mut buf, Duration::from_secs(1))?;
-
-            parse_position(&buf)
-        })?
+        self.0?
+            .query_until(b"\x1B[6n", b"R")
+            .and_then(|report| parse_position(&report))
     }
 
     #[must_use]
     pub fn erase_below(self) -> Self {
-        self.chain(|t| write!(t.stdout_mut(), "\x1B[0J"))
+        self.chain(|t| t.write_capability("ed", &[], format_args!("\x1B[0J")))
     }
 
     #[must_use]
@@ -111,12 +106,12 @@ impl<'a, 'b> Cursor<'a, 'b> {
 
     #[must_use]
     pub fn erase_forward(self) -> Self {
-        self.chain(|t| write!(t.stdout_mut(), "\x1B[0K"))
+        self.chain(|t| t.write_capability("el", &[], format_args!("\x1B[0K")))
     }
 
     #[must_use]
     pub fn erase_backward(self) -> Self {
-        self.chain(|t| write!(t.stdout_mut(), "\x1B[1K"))
+        self.chain(|t| t.write_capability("el1", &[], format_args!("\x1B[1K")))
     }
 
     #[must_use]
@@ -128,12 +123,24 @@ impl<'a, 'b> Cursor<'a, 'b> {
     #[must_use]
     fn chain<F>(self, f: F) -> Self
     where
-        F: FnOnce(&mut Term) -> io::Result<()>,
+        F: FnOnce(&mut Term<'a, W, E>) -> io::Result<()>,
     {
         Self(self.0.and_then(|t| f(t).map(|_| t)))
     }
 }
 
+pub(crate) fn write_position<W: Write, E: Write>(
+    t: &mut Term<'_, W, E>,
+    pos: Vector2<u16>,
+) -> io::Result<()> {
+    let [x, y] = pos.into_inner();
+    t.write_capability(
+        "cup",
+        &[y.into(), x.into()],
+        format_args!("\x1B[{};{}H", y.saturating_add(1), x.saturating_add(1)),
+    )
+}
+
 fn parse_position(bytes: &[u8]) -> io::Result<Vector2<u16>> {
     let delimiter = bytes
         .iter()
@@ -171,5 +178,5 @@ fn make_err<E>(err: E) -> io::Error
 where
     E: Into<Box<dyn error::Error + Send + Sync>>,
 {
-    io::Error::new(io::ErrorKind::Other, err)
+    io::Error::other(err)
 }
@@ -3,28 +3,30 @@ use crate::printer::Printer;
 use crate::screen::Screen;
 use crate::{Mode, Term, UpdatePolicy};
 
-use std::io::{self, Write};
+use std::io::{self, StderrLock, StdoutLock, Write};
 
-pub struct Flow<'a: 'b, 'b>(pub(crate) io::Result<&'b mut Term<'a>>);
+pub struct Flow<'a: 'b, 'b, W: Write = StdoutLock<'a>, E: Write = StderrLock<'a>>(
+    pub(crate) io::Result<&'b mut Term<'a, W, E>>,
+);
 
-impl<'a, 'b> Flow<'a, 'b> {
+impl<'a, 'b, W: Write, E: Write> Flow<'a, 'b, W, E> {
     #[must_use]
-    pub fn cursor(self) -> Cursor<'a, 'b> {
+    pub fn cursor(self) -> Cursor<'a, 'b, W, E> {
         Cursor(self.0)
     }
 
     #[must_use]
-    pub fn printer(self) -> Printer<'a, 'b> {
+    pub fn printer(self) -> Printer<'a, 'b, W, E> {
         Printer(self.0)
     }
 
     #[must_use]
-    pub fn screen(self) -> Screen<'a, 'b> {
+    pub fn screen(self) -> Screen<'a, 'b, W, E> {
         Screen(self.0)
     }
 
     pub fn set_mode(self, mode: Mode) -> Self {
-        self.chain(|_| crate::set_mode(mode, UpdatePolicy::Lazy))
+        self.chain(|t| t.set_mode_with(mode, UpdatePolicy::Lazy))
     }
 
     pub fn flush(self) -> io::Result<()> {
@@ -35,7 +37,7 @@ impl<'a, 'b> Flow<'a, 'b> {
     #[must_use]
     fn chain<F>(self, f: F) -> Self
     where
-        F: FnOnce(&mut Term) -> io::Result<()>,
+        F: FnOnce(&mut Term<'a, W, E>) -> io::Result<()>,
     {
         Self(self.0.and_then(|t| f(t).map(|_| t)))
     }
@@ -0,0 +1,124 @@
+pub mod iterm;
+pub mod kitty;
+pub mod sixel;
+
+use std::io;
+
+// Ordered from the most to the least capable protocol.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Protocol {
+    Kitty,
+    Iterm,
+    Sixel,
+}
+
+// An RGBA image, 8 bits per channel, stored row by row.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Image<'a> {
+    width: u32,
+    height: u32,
+    pixels: &'a [u8],
+}
+
+impl<'a> Image<'a> {
+    pub fn new(width: u32, height: u32, pixels: &'a [u8]) -> io::Result<Self> {
+        let len = (width as usize)
+            .checked_mul(height as usize)
+            .and_then(|n| n.checked_mul(4));
+
+        if len == Some(pixels.len()) {
+            Ok(Self {
+                width,
+                height,
+                pixels,
+            })
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Expected {}x{} RGBA pixels, got {} bytes",
+                    width,
+                    height,
+                    pixels.len()
+                ),
+            ))
+        }
+    }
+
+    #[must_use]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    #[must_use]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    #[must_use]
+    pub fn pixels(&self) -> &'a [u8] {
+        self.pixels
+    }
+
+    // Returns `None` for coordinates outside of the image.
+    #[must_use]
+    pub fn pixel(&self, x: u32, y: u32) -> Option<[u8; 4]> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+
+        let i = (y as usize * self.width as usize + x as usize) * 4;
+        self.pixels[i..i + 4].try_into().ok()
+    }
+}
+
+// From: (https://datatracker.ietf.org/doc/html/rfc4648#section-4)
+#[must_use]
+pub(crate) fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, b)| n | u32::from(*b) << (16 - 8 * i));
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(char::from(ALPHABET[(n >> (18 - 6 * i)) as usize & 0x3F]));
+            } else {
+                out.push('=');
+            }
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64_encoding() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+        assert_eq!(base64(&[0xFF, 0xEF]), "/+8=");
+    }
+
+    #[test]
+    fn pixels() {
+        let pixels = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12];
+        let image = Image::new(3, 1, &pixels).unwrap();
+
+        assert_eq!(image.pixel(0, 0), Some([1, 2, 3, 4]));
+        assert_eq!(image.pixel(2, 0), Some([9, 10, 11, 12]));
+        assert_eq!(image.pixel(3, 0), None);
+        assert_eq!(image.pixel(0, 1), None);
+        assert_eq!(image.pixel(u32::MAX, u32::MAX), None);
+    }
+}
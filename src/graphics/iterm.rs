@@ -0,0 +1,201 @@
+use crate::graphics::{self, Image};
+
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, Write};
+
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Dimension {
+    #[default]
+    Auto,
+    Cells(u16),
+    Pixels(u32),
+    Percent(u8),
+}
+
+impl Display for Dimension {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Auto => write!(f, "auto"),
+            Self::Cells(n) => write!(f, "{}", n),
+            Self::Pixels(n) => write!(f, "{}px", n),
+            Self::Percent(n) => write!(f, "{}%", n),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Options {
+    pub name: Option<String>,
+    pub width: Dimension,
+    pub height: Dimension,
+    pub preserve_aspect_ratio: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            name: None,
+            width: Dimension::Auto,
+            height: Dimension::Auto,
+            preserve_aspect_ratio: true,
+        }
+    }
+}
+
+impl Options {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    #[must_use]
+    pub fn with_width(mut self, width: Dimension) -> Self {
+        self.width = width;
+        self
+    }
+
+    #[must_use]
+    pub fn with_height(mut self, height: Dimension) -> Self {
+        self.height = height;
+        self
+    }
+
+    #[must_use]
+    pub fn with_preserve_aspect_ratio(mut self, preserve: bool) -> Self {
+        self.preserve_aspect_ratio = preserve;
+        self
+    }
+}
+
+// From: (https://iterm2.com/documentation-images.html)
+// `file` holds the content of an image file in any format the terminal
+// is able to decode (PNG, JPEG, GIF, ...).
+pub fn encode<W: Write + ?Sized>(out: &mut W, file: &[u8], options: &Options) -> io::Result<()> {
+    write!(out, "\x1B]1337;File=inline=1;size={}", file.len())?;
+
+    if let Some(name) = &options.name {
+        write!(out, ";name={}", graphics::base64(name.as_bytes()))?;
+    }
+
+    write!(
+        out,
+        ";width={};height={};preserveAspectRatio={}:{}\x07",
+        options.width,
+        options.height,
+        u8::from(options.preserve_aspect_ratio),
+        graphics::base64(file)
+    )
+}
+
+// Encodes the image as an uncompressed PNG file, iTerm2 doesn't accept raw pixels.
+// From: (https://www.w3.org/TR/png/)
+#[must_use]
+pub fn png(image: &Image) -> Vec<u8> {
+    let stride = image.width() as usize * 4;
+    let mut raw = Vec::with_capacity((stride + 1) * image.height() as usize);
+    for row in image.pixels().chunks_exact(stride.max(1)) {
+        raw.push(0); // filter type: none
+        raw.extend_from_slice(row);
+    }
+
+    // zlib stream made of stored (non compressed) deflate blocks.
+    let mut zlib = vec![0x78, 0x01];
+    let mut blocks = raw.chunks(u16::MAX as usize).peekable();
+    if blocks.peek().is_none() {
+        zlib.extend([1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let len = block.len() as u16;
+        zlib.push(u8::from(blocks.peek().is_none()));
+        zlib.extend(len.to_le_bytes());
+        zlib.extend((!len).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend(adler32(&raw).to_be_bytes());
+
+    let mut header = Vec::with_capacity(13);
+    header.extend(image.width().to_be_bytes());
+    header.extend(image.height().to_be_bytes());
+    header.extend([8, 6, 0, 0, 0]); // 8 bits per channel, RGBA, no interlace
+
+    let mut out = b"\x89PNG\r\n\x1A\n".to_vec();
+    write_chunk(&mut out, b"IHDR", &header);
+    write_chunk(&mut out, b"IDAT", &zlib);
+    write_chunk(&mut out, b"IEND", &[]);
+    out
+}
+
+fn write_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend((data.len() as u32).to_be_bytes());
+    out.extend(kind);
+    out.extend(data);
+    out.extend(crc32(kind.iter().chain(data)).to_be_bytes());
+}
+
+#[must_use]
+fn crc32<'a>(bytes: impl Iterator<Item = &'a u8>) -> u32 {
+    !bytes.fold(!0u32, |crc, byte| {
+        (0..8).fold(crc ^ u32::from(*byte), |crc, _| {
+            (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg())
+        })
+    })
+}
+
+#[must_use]
+fn adler32(bytes: &[u8]) -> u32 {
+    let (a, b) = bytes.iter().fold((1u32, 0u32), |(a, b), byte| {
+        let a = (a + u32::from(*byte)) % 65521;
+        (a, (b + a) % 65521)
+    });
+    b << 16 | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encoding() {
+        let mut out = Vec::new();
+        let options = Options::new()
+            .with_name("a.png")
+            .with_width(Dimension::Cells(10))
+            .with_height(Dimension::Percent(50))
+            .with_preserve_aspect_ratio(false);
+        encode(&mut out, b"foo", &options).unwrap();
+
+        assert_eq!(
+            out,
+            b"\x1B]1337;File=inline=1;size=3;name=YS5wbmc=;width=10;height=50%;preserveAspectRatio=0:Zm9v\x07"
+        );
+
+        let mut out = Vec::new();
+        encode(
+            &mut out,
+            b"",
+            &Options::new().with_width(Dimension::Pixels(8)),
+        )
+        .unwrap();
+        assert_eq!(
+            out,
+            b"\x1B]1337;File=inline=1;size=0;width=8px;height=auto;preserveAspectRatio=1:\x07"
+        );
+    }
+
+    #[test]
+    fn checksums() {
+        assert_eq!(crc32(b"IEND".iter()), 0xAE42_6082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+
+        let pixels = [0xFF; 2 * 2 * 4];
+        let png = png(&Image::new(2, 2, &pixels).unwrap());
+        assert!(png.starts_with(b"\x89PNG\r\n\x1A\n\0\0\0\x0DIHDR\0\0\0\x02\0\0\0\x02\x08\x06"));
+        assert!(png.ends_with(b"\0\0\0\0IEND\xAE\x42\x60\x82"));
+    }
+}
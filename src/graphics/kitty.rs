@@ -0,0 +1,167 @@
+use crate::graphics::{self, Image};
+use crate::vector::Vector2;
+
+use std::io::{self, Write};
+
+// From: (https://sw.kovidgoyal.net/kitty/graphics-protocol/#remote-client)
+// >>> the client must split the data into chunks of at most 4096 bytes
+const CHUNK_SIZE: usize = 4096;
+
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Placement {
+    pub id: u32,
+    pub z_index: i32,
+    pub size: Option<Vector2<u16>>,
+}
+
+impl Placement {
+    #[must_use]
+    pub fn new(id: u32) -> Self {
+        Self {
+            id,
+            ..Default::default()
+        }
+    }
+
+    #[must_use]
+    pub fn with_z_index(mut self, z_index: i32) -> Self {
+        self.z_index = z_index;
+        self
+    }
+
+    // Scales the image to fit the given number of columns and rows.
+    #[must_use]
+    pub fn with_size(mut self, size: impl Into<Vector2<u16>>) -> Self {
+        self.size = Some(size.into());
+        self
+    }
+}
+
+// Uploads the image to the terminal without displaying it, responses
+// are suppressed (`q=2`) since nobody would read them from stdin.
+pub fn transmit<W: Write + ?Sized>(out: &mut W, image_id: u32, image: &Image) -> io::Result<()> {
+    write!(
+        out,
+        "\x1B_Ga=t,f=32,s={},v={},i={},q=2",
+        image.width(),
+        image.height(),
+        image_id
+    )?;
+
+    write_payload(out, image.pixels())
+}
+
+// Transmits and displays the image at the cursor position at once, the
+// image gets no id and so it can be deleted only by clearing the screen.
+pub fn display<W: Write + ?Sized>(out: &mut W, image: &Image) -> io::Result<()> {
+    write!(
+        out,
+        "\x1B_Ga=T,f=32,s={},v={},q=2",
+        image.width(),
+        image.height()
+    )?;
+
+    write_payload(out, image.pixels())
+}
+
+// Displays a transmitted image at the cursor position, placing it again
+// with the same placement id moves it rather than duplicating it.
+pub fn place<W: Write + ?Sized>(
+    out: &mut W,
+    image_id: u32,
+    placement: &Placement,
+) -> io::Result<()> {
+    write!(
+        out,
+        "\x1B_Ga=p,i={},p={},z={},q=2",
+        image_id, placement.id, placement.z_index
+    )?;
+
+    if let Some(size) = placement.size {
+        let [columns, rows] = size.into_inner();
+        write!(out, ",c={},r={}", columns, rows)?;
+    }
+
+    out.write_all(b"\x1B\\")
+}
+
+// Terminates the control data written so far, splitting the base64 encoded
+// payload into as many escape sequences as needed.
+fn write_payload<W: Write + ?Sized>(out: &mut W, payload: &[u8]) -> io::Result<()> {
+    let payload = graphics::base64(payload);
+    let mut chunks = payload.as_bytes().chunks(CHUNK_SIZE).peekable();
+
+    if chunks.peek().is_none() {
+        return out.write_all(b"\x1B\\");
+    }
+
+    let mut first = true;
+    while let Some(chunk) = chunks.next() {
+        let more = u8::from(chunks.peek().is_some());
+        if !std::mem::take(&mut first) {
+            write!(out, "\x1B_Gq=2")?;
+        }
+        write!(out, ",m={};", more)?;
+        out.write_all(chunk)?;
+        out.write_all(b"\x1B\\")?;
+    }
+
+    Ok(())
+}
+
+pub fn delete_placement<W: Write + ?Sized>(
+    out: &mut W,
+    image_id: u32,
+    placement_id: u32,
+) -> io::Result<()> {
+    write!(
+        out,
+        "\x1B_Ga=d,d=i,i={},p={},q=2\x1B\\",
+        image_id, placement_id
+    )
+}
+
+// Deletes all the placements of the image and frees its data.
+pub fn delete_image<W: Write + ?Sized>(out: &mut W, image_id: u32) -> io::Result<()> {
+    write!(out, "\x1B_Ga=d,d=I,i={},q=2\x1B\\", image_id)
+}
+
+pub fn delete_placements<W: Write + ?Sized>(out: &mut W) -> io::Result<()> {
+    out.write_all(b"\x1B_Ga=d,d=a,q=2\x1B\\")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands() {
+        let pixels = vec![0x80; 64 * 32 * 4];
+        let image = Image::new(64, 32, &pixels).unwrap();
+
+        let mut out = Vec::new();
+        transmit(&mut out, 7, &image).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let chunks = out.split_terminator("\x1B\\").collect::<Vec<_>>();
+
+        assert_eq!(chunks.len(), 3);
+        assert!(chunks[0].starts_with("\x1B_Ga=t,f=32,s=64,v=32,i=7,q=2,m=1;gICA"));
+        assert!(chunks[1].starts_with("\x1B_Gq=2,m=1;"));
+        assert!(chunks[2].starts_with("\x1B_Gq=2,m=0;"));
+        assert!(chunks.iter().all(|c| c.len() <= CHUNK_SIZE + 64));
+
+        let mut out = Vec::new();
+        let placement = Placement::new(3).with_z_index(-1).with_size([10, 5]);
+        place(&mut out, 7, &placement).unwrap();
+        delete_placement(&mut out, 7, 3).unwrap();
+        delete_image(&mut out, 7).unwrap();
+        delete_placements(&mut out).unwrap();
+        assert_eq!(
+            out,
+            b"\x1B_Ga=p,i=7,p=3,z=-1,q=2,c=10,r=5\x1B\\\
+              \x1B_Ga=d,d=i,i=7,p=3,q=2\x1B\\\
+              \x1B_Ga=d,d=I,i=7,q=2\x1B\\\
+              \x1B_Ga=d,d=a,q=2\x1B\\"
+        );
+    }
+}
@@ -0,0 +1,244 @@
+use crate::graphics::Image;
+
+use std::collections::{BTreeSet, HashMap};
+use std::io::{self, Write};
+
+pub const MAX_COLORS: usize = 256;
+
+// From: (https://vt100.net/docs/vt510-rm/DA1.html)
+// >>> 4 Sixel graphics.
+const SIXEL_ATTRIBUTE: u16 = 4;
+
+// Pixels whose alpha is below this threshold are left untouched.
+const ALPHA_THRESHOLD: u8 = 128;
+
+#[must_use]
+pub fn is_supported(device_attributes: &[u16]) -> bool {
+    device_attributes.contains(&SIXEL_ATTRIBUTE)
+}
+
+// From: (https://vt100.net/docs/vt3xx-gp/chapter14.html)
+pub fn encode<W: Write + ?Sized>(out: &mut W, image: &Image, colors: usize) -> io::Result<()> {
+    let (palette, indices) = quantize(image, colors.clamp(1, MAX_COLORS));
+    let [width, height] = [image.width() as usize, image.height() as usize];
+
+    // P2 = 1 leaves pixels with no color assigned (transparent ones) untouched.
+    write!(out, "\x1BP0;1;0q\"1;1;{};{}", width, height)?;
+
+    for (i, [r, g, b]) in palette.iter().enumerate() {
+        write!(
+            out,
+            "#{};2;{};{};{}",
+            i,
+            percent(*r),
+            percent(*g),
+            percent(*b)
+        )?;
+    }
+
+    for top in (0..height).step_by(6) {
+        let band = top..(top + 6).min(height);
+        let used = band
+            .clone()
+            .flat_map(|y| &indices[y * width..(y + 1) * width])
+            .flatten()
+            .collect::<BTreeSet<_>>();
+
+        for (n, color) in used.into_iter().enumerate() {
+            if n > 0 {
+                out.write_all(b"$")?;
+            }
+            write!(out, "#{}", color)?;
+
+            let sixels = (0..width).map(|x| {
+                band.clone()
+                    .enumerate()
+                    .filter(|(_, y)| indices[y * width + x] == Some(*color))
+                    .fold(0u8, |bits, (dy, _)| bits | 1 << dy)
+            });
+
+            write_runs(out, sixels)?;
+        }
+
+        out.write_all(b"-")?;
+    }
+
+    out.write_all(b"\x1B\\")
+}
+
+// Run-length encodes sixels, trailing empty sixels are omitted.
+fn write_runs<W: Write + ?Sized>(out: &mut W, sixels: impl Iterator<Item = u8>) -> io::Result<()> {
+    let mut run: Option<(u8, usize)> = None;
+    let mut blanks = 0;
+
+    for bits in sixels {
+        if bits == 0 {
+            blanks += 1;
+            continue;
+        }
+
+        if blanks > 0 {
+            if let Some((bits, count)) = run.take() {
+                write_run(out, bits, count)?;
+            }
+            write_run(out, 0, std::mem::take(&mut blanks))?;
+        }
+
+        run = match run {
+            Some((b, count)) if b == bits => Some((b, count + 1)),
+            Some((b, count)) => {
+                write_run(out, b, count)?;
+                Some((bits, 1))
+            }
+            None => Some((bits, 1)),
+        };
+    }
+
+    match run {
+        Some((bits, count)) => write_run(out, bits, count),
+        None => Ok(()),
+    }
+}
+
+fn write_run<W: Write + ?Sized>(out: &mut W, bits: u8, count: usize) -> io::Result<()> {
+    match count {
+        0 => Ok(()),
+        1..=3 => out.write_all(&vec![b'?' + bits; count]),
+        _ => write!(out, "!{}{}", count, char::from(b'?' + bits)),
+    }
+}
+
+#[must_use]
+fn percent(channel: u8) -> u32 {
+    (u32::from(channel) * 100 + 127) / 255
+}
+
+// Median cut quantization, returns the palette and, for each pixel,
+// the index of its color in the palette (`None` for transparent pixels).
+fn quantize(image: &Image, colors: usize) -> (Vec<[u8; 3]>, Vec<Option<usize>>) {
+    let mut histogram = HashMap::<[u8; 3], usize>::new();
+    image
+        .pixels()
+        .chunks_exact(4)
+        .filter(|p| p[3] >= ALPHA_THRESHOLD)
+        .for_each(|p| *histogram.entry([p[0], p[1], p[2]]).or_default() += 1);
+
+    let mut boxes = vec![histogram.into_iter().collect::<Vec<_>>()];
+
+    while boxes.len() < colors {
+        let widest = boxes
+            .iter_mut()
+            .filter(|b| b.len() > 1)
+            .map(|b| {
+                let (channel, range) = (0..3)
+                    .map(|c| {
+                        let (min, max) = b.iter().fold((u8::MAX, u8::MIN), |(min, max), e| {
+                            (min.min(e.0[c]), max.max(e.0[c]))
+                        });
+                        (c, max - min)
+                    })
+                    .max_by_key(|(_, range)| *range)
+                    .unwrap_or_default();
+                (b, channel, range)
+            })
+            .max_by_key(|(_, _, range)| *range);
+
+        let Some((bucket, channel, _)) = widest else {
+            break;
+        };
+
+        // Split at the median pixel, keeping both halves non empty.
+        bucket.sort_unstable_by_key(|e| e.0[channel]);
+        let half = bucket.iter().map(|e| e.1).sum::<usize>() / 2;
+        let mut seen = 0;
+        let at = bucket
+            .iter()
+            .position(|e| {
+                seen += e.1;
+                seen > half
+            })
+            .unwrap_or(0)
+            .clamp(1, bucket.len() - 1);
+
+        let upper = bucket.split_off(at);
+        boxes.push(upper);
+    }
+
+    let mut lookup = HashMap::new();
+    let palette = boxes
+        .iter()
+        .filter(|b| !b.is_empty())
+        .enumerate()
+        .map(|(i, b)| {
+            let total = b.iter().map(|e| e.1).sum::<usize>();
+            let mut sum = [0usize; 3];
+            for ([r, g, b], count) in b {
+                lookup.insert([*r, *g, *b], i);
+                sum[0] += usize::from(*r) * count;
+                sum[1] += usize::from(*g) * count;
+                sum[2] += usize::from(*b) * count;
+            }
+            sum.map(|c| (c / total) as u8)
+        })
+        .collect();
+
+    let indices = image
+        .pixels()
+        .chunks_exact(4)
+        .map(|p| {
+            Some(p)
+                .filter(|p| p[3] >= ALPHA_THRESHOLD)
+                .and_then(|p| lookup.get(&[p[0], p[1], p[2]]).copied())
+        })
+        .collect();
+
+    (palette, indices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encoding() {
+        let (red, blue, none) = ([255, 0, 0, 255], [0, 0, 255, 255], [0, 0, 0, 0]);
+        let mut pixels = Vec::new();
+        for y in 0..7 {
+            for x in 0..5 {
+                pixels.extend(match (x, y) {
+                    (4, _) => none,
+                    (_, 6) => blue,
+                    _ => red,
+                });
+            }
+        }
+
+        let image = Image::new(5, 7, &pixels).unwrap();
+        let mut out = Vec::new();
+        encode(&mut out, &image, 256).unwrap();
+
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("\x1BP0;1;0q\"1;1;5;7"));
+        assert!(out.ends_with("\x1B\\"));
+        assert!(out.contains(";2;100;0;0"));
+        assert!(out.contains(";2;0;0;100"));
+
+        let red = if out.contains("#0;2;100;0;0") { 0 } else { 1 };
+        assert!(out.contains(&format!("#{}!4~-#{}!4@-", red, 1 - red)));
+    }
+
+    #[test]
+    fn quantization() {
+        let pixels = (0..=255u8)
+            .flat_map(|c| [c, 255 - c, c / 2, 255])
+            .collect::<Vec<_>>();
+        let image = Image::new(16, 16, &pixels).unwrap();
+
+        let (palette, indices) = quantize(&image, 16);
+        assert_eq!(palette.len(), 16);
+        assert!(indices.iter().all(|i| i.is_some_and(|i| i < 16)));
+
+        let (palette, _) = quantize(&image, 256);
+        assert_eq!(palette.len(), 256);
+    }
+}
@@ -1,65 +1,147 @@
 #![feature(deadline_api)]
 
+pub mod canvas;
 pub mod cursor;
 pub mod flow;
+pub mod graphics;
 pub mod nio;
 pub mod printer;
+pub mod restore;
 pub mod screen;
+pub mod terminfo;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod text;
 pub mod vector;
 
 use crate::cursor::Cursor;
 use crate::flow::Flow;
-use crate::nio::Stdin;
-use crate::printer::Printer;
-use crate::screen::{Buffer, Screen};
+use crate::graphics::{sixel, Protocol};
+use crate::nio::{ReadNonblock, Stdin};
+use crate::printer::{Color, Colors, Printer};
+use crate::screen::{Buffer, Screen, Viewport};
+use crate::terminfo::{Param, TermInfo};
 use crate::vector::Vector2;
 
-use std::io::{self, StderrLock, StdoutLock, Write};
+use std::env;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, StderrLock, StdoutLock, Write};
 use std::mem::MaybeUninit;
-use std::os::unix::io::{AsRawFd, RawFd};
-use std::sync::Once;
+use std::os::unix::io::{AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
+use std::ptr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 
-use libc::{c_int, ioctl, winsize, TIOCGWINSZ};
-use termios::{cfmakeraw, tcsetattr, Termios, TCSAFLUSH, TCSANOW};
+use libc::{c_int, ioctl, winsize, SIGTSTP, TIOCGWINSZ};
+use termios::{
+    cfmakeraw, tcflag_t, tcsetattr, Termios, ECHO, ICANON, ICRNL, ISIG, IXON, OPOST, TCSAFLUSH,
+    TCSANOW, VMIN, VTIME,
+};
 
-pub struct Term<'a> {
+// Renders to `W`, the standard output unless created through `open_tty`
+// or `with_writer`, the `Device` provides the size and the mode of the
+// terminal `W` writes to, if any. Queries are written to `E`.
+pub struct Term<'a, W: Write = StdoutLock<'a>, E: Write = StderrLock<'a>> {
     stdin: Stdin,
-    stdout: StdoutLock<'a>,
-    stderr: StderrLock<'a>,
+    stdout: W,
+    stderr: E,
+    device: Option<Box<dyn Device + 'a>>,
+    // Whether `Term` drives the terminal of the process, through the
+    // standard streams or `from_fd`.
+    tty: bool,
+    terminfo: Option<TermInfo>,
+    // Last mode set through the device, applied again on resume.
+    mode: Mode,
+    recolored: Recolored,
+    images: Vec<u32>,
+    viewport: Viewport,
+    viewport_origin: Vector2<u16>,
 }
 
 impl<'a> Term<'a> {
-    pub fn open(mut stdout: StdoutLock<'a>, stderr: StderrLock<'a>) -> io::Result<Self> {
-        // trigger DEFAULT_STATE initialization!!!
-        default_state()?;
+    pub fn open(stdout: StdoutLock<'a>, stderr: StderrLock<'a>) -> io::Result<Self> {
+        Self::with_io(Claim::Standard, |_| Ok((nio::stdin()?, stdout, stderr)))
+    }
+}
 
-        // flush pending outputs
-        stdout.flush()?;
+impl<'a, W: Write> Term<'a, W, io::Sink> {
+    // Renders into `writer` (e.g. a `Vec<u8>`, a socket or a pty master),
+    // leaving the terminal of the process untouched. Without a device,
+    // see `with_device`, the size is unknown and modes are not applied,
+    // queries fail since there is no one to answer them.
+    pub fn with_writer(writer: W) -> Self {
+        Self {
+            stdin: nio::empty(),
+            stdout: writer,
+            stderr: io::sink(),
+            device: None,
+            tty: false,
+            terminfo: None,
+            mode: Mode::Default,
+            recolored: Recolored::default(),
+            images: Vec::new(),
+            viewport: Viewport::default(),
+            viewport_origin: [0, 0].into(),
+        }
+    }
+}
 
-        Ok(Self {
-            stdin: nio::stdin(),
-            stdout,
-            stderr,
-        })
+impl<'a, W: Write, E: Write> Term<'a, W, E> {
+    #[must_use]
+    pub fn with_device(mut self, device: impl Device + 'a) -> Self {
+        self.device = Some(Box::new(device));
+        self
+    }
+
+    // The terminal is released when `Term` gets dropped, or right away if
+    // `io` fails.
+    fn with_io<F>(claim: Claim, io: F) -> io::Result<Self>
+    where
+        F: FnOnce(RawFd) -> io::Result<(Stdin, W, E)>,
+    {
+        let fd = crate::claim(claim)?;
+        let init = || {
+            let (stdin, mut stdout, stderr) = io(fd)?;
+
+            // flush pending outputs
+            stdout.flush()?;
+
+            Ok(Self {
+                stdin,
+                stdout,
+                stderr,
+                device: Some(Box::new(Tty)),
+                tty: true,
+                terminfo: TermInfo::from_env().ok(),
+                mode: Mode::Default,
+                recolored: Recolored::default(),
+                images: Vec::new(),
+                viewport: Viewport::default(),
+                viewport_origin: [0, 0].into(),
+            })
+        };
+
+        init().inspect_err(|_| release())
     }
 
     #[must_use]
-    pub fn cursor(&mut self) -> Cursor<'a, '_> {
+    pub fn cursor(&mut self) -> Cursor<'a, '_, W, E> {
         Cursor(Ok(self))
     }
 
     #[must_use]
-    pub fn printer(&mut self) -> Printer<'a, '_> {
+    pub fn printer(&mut self) -> Printer<'a, '_, W, E> {
         Printer(Ok(self))
     }
 
     #[must_use]
-    pub fn screen(&mut self) -> Screen<'a, '_> {
+    pub fn screen(&mut self) -> Screen<'a, '_, W, E> {
         Screen(Ok(self))
     }
 
     #[must_use]
-    pub fn flow(&mut self) -> Flow<'a, '_> {
+    pub fn flow(&mut self) -> Flow<'a, '_, W, E> {
         Flow(Ok(self))
     }
 
@@ -71,50 +153,398 @@ impl<'a> Term<'a> {
         &mut self.stdin
     }
 
-    pub fn stdout(&self) -> &StdoutLock<'a> {
+    pub fn stdout(&self) -> &W {
         &self.stdout
     }
 
-    pub fn stdout_mut(&mut self) -> &mut StdoutLock<'a> {
+    pub fn stdout_mut(&mut self) -> &mut W {
         &mut self.stdout
     }
 
-    pub fn stderr(&self) -> &StderrLock<'a> {
+    pub fn stderr(&self) -> &E {
         &self.stderr
     }
 
-    pub fn stderr_mut(&mut self) -> &mut StderrLock<'a> {
+    pub fn stderr_mut(&mut self) -> &mut E {
         &mut self.stderr
     }
 
+    pub fn terminfo(&self) -> Option<&TermInfo> {
+        self.terminfo.as_ref()
+    }
+
+    pub fn set_terminfo(&mut self, terminfo: Option<TermInfo>) {
+        self.terminfo = terminfo;
+    }
+
+    pub fn viewport(&self) -> Viewport {
+        self.viewport
+    }
+
+    // Position of the top left cell of the viewport.
+    pub fn viewport_origin(&self) -> Vector2<u16> {
+        self.viewport_origin
+    }
+
+    pub fn viewport_size(&self) -> io::Result<Vector2<u16>> {
+        let size = self.size()?;
+        match self.viewport {
+            Viewport::Fullscreen => Ok(size),
+            Viewport::Inline { height } => Ok([size[0], height.min(size[1])].into()),
+        }
+    }
+
+    // Does nothing without a device.
     pub fn set_mode(&mut self, mode: Mode) -> io::Result<()> {
-        crate::set_mode(mode, UpdatePolicy::Now)
+        self.set_mode_with(mode, UpdatePolicy::Now)
+    }
+
+    pub(crate) fn set_mode_with(&mut self, mode: Mode, policy: UpdatePolicy) -> io::Result<()> {
+        if let Some(device) = self.device.as_mut() {
+            device.set_mode(mode, policy)?;
+        }
+        self.mode = mode;
+        Ok(())
+    }
+
+    // Switches to `mode` until the returned guard gets dropped.
+    pub fn enter(&mut self, mode: Mode) -> io::Result<ModeGuard> {
+        self.tty()?;
+        ModeGuard::enter(mode)
+    }
+
+    // Hands the terminal back to the shell and stops the process, as Ctrl-Z
+    // would do outside of raw mode (where it's read as `0x1A` instead).
+    // Redefined colors are reset and kitty placements deleted while stopped.
+    // Once resumed (SIGCONT), the mode last set, the buffer, the cursor
+    // visibility and the colors are restored, and the caller must redraw
+    // everything from scratch, images included, since the shell may have
+    // overwritten the screen in the meantime.
+    pub fn suspend(&mut self) -> io::Result<Resumed> {
+        self.tty()?;
+        let mode = self.mode;
+        let alternate = restore::alternate_buffer();
+        let hidden = restore::cursor_hidden();
+        let recolored = self.recolored.clone();
+
+        let mut screen = self.cursor().show().printer().restore().screen();
+        if restore::images_placed() {
+            screen = screen.delete_placements();
+        }
+        screen = screen.reset_colors();
+        if alternate {
+            screen = screen.set_buffer(Buffer::Primary);
+        }
+        screen.flush()?;
+        self.set_mode(Mode::Default)?;
+
+        if unsafe { libc::raise(SIGTSTP) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        self.set_mode(mode)?;
+        let mut screen = self.screen();
+        if alternate {
+            screen = screen.set_buffer(Buffer::Alternate);
+        }
+        if hidden {
+            screen = screen.cursor().hide().screen();
+        }
+        for (index, color) in recolored.palette {
+            screen = screen.set_palette(index, color);
+        }
+        if let Some(color) = recolored.foreground {
+            screen = screen.set_default_foreground(color);
+        }
+        if let Some(color) = recolored.background {
+            screen = screen.set_default_background(color);
+        }
+        if let Some(color) = recolored.cursor {
+            screen = screen.set_cursor_color(color);
+        }
+        screen.flush()?;
+
+        Ok(Resumed { size: self.size()? })
     }
 
     pub fn size(&self) -> io::Result<Vector2<u16>> {
-        crate::size()
+        self.device()?.size()
+    }
+
+    pub fn pixel_size(&mut self) -> io::Result<Vector2<u16>> {
+        match self.device()?.pixel_size()?.into_inner() {
+            [x @ 1..=u16::MAX, y @ 1..=u16::MAX] => Ok([x, y].into()),
+            _ => self
+                .query(b"\x1B[14t")
+                .and_then(|report| parse_window_report(&report, 4)),
+        }
+    }
+
+    pub fn cell_size(&mut self) -> io::Result<Vector2<u16>> {
+        let size = self.size()?.into_inner();
+        match [size, self.device()?.pixel_size()?.into_inner()] {
+            [[cols @ 1..=u16::MAX, rows @ 1..=u16::MAX], [x @ 1..=u16::MAX, y @ 1..=u16::MAX]] => {
+                Ok([x / cols, y / rows].into())
+            }
+            // Not every terminal answers `CSI 16t`, derive the cell size from
+            // the text area size in that case.
+            _ => self
+                .query(b"\x1B[16t")
+                .and_then(|report| parse_window_report(&report, 6))
+                .or_else(|err| match size {
+                    [cols @ 1..=u16::MAX, rows @ 1..=u16::MAX] => {
+                        Ok(self.pixel_size()? / [cols, rows])
+                    }
+                    _ => Err(err),
+                }),
+        }
+    }
+
+    // Colors left unanswered by the terminal are `Color::Default`, as some
+    // terminals report only one of the two.
+    pub fn query_colors(&mut self) -> io::Result<Colors> {
+        Ok(Colors {
+            foreground: self.query_dynamic_color("10")?,
+            background: self.query_dynamic_color("11")?,
+        })
+    }
+
+    pub fn query_palette(&mut self, index: u8) -> io::Result<Color> {
+        let report = self.query(format!("\x1B]4;{};?\x1B\\", index).as_bytes())?;
+        printer::parse_color_report(&report, &format!("4;{}", index))
+    }
+
+    fn query_dynamic_color(&mut self, code: &str) -> io::Result<Color> {
+        let report = self.query(format!("\x1B]{};?\x1B\\", code).as_bytes())?;
+        Ok(printer::parse_color_report(&report, code).unwrap_or_default())
+    }
+
+    // From: (https://vt100.net/docs/vt510-rm/DA1.html)
+    pub fn device_attributes(&mut self) -> io::Result<Vec<u16>> {
+        self.query(b"\x1B[c")
+            .and_then(|report| parse_device_attributes(&report))
+    }
+
+    // Kitty is detected querying support for a tiny image, iTerm2 through
+    // the environment and sixel looking at the device attributes.
+    // From: (https://sw.kovidgoyal.net/kitty/graphics-protocol/#querying-support-and-available-transmission-mediums)
+    pub fn graphics_protocol(&mut self) -> io::Result<Option<Protocol>> {
+        let report = self.query(b"\x1B_Gi=31,s=1,v=1,a=q,t=d,f=24;AAAA\x1B\\\x1B[c")?;
+
+        if report.windows(9).any(|w| w == b"_Gi=31;OK") {
+            return Ok(Some(Protocol::Kitty));
+        }
+
+        let iterm = ["TERM_PROGRAM", "LC_TERMINAL"].into_iter().any(|key| {
+            env::var(key)
+                .is_ok_and(|value| matches!(value.as_str(), "iTerm.app" | "iTerm2" | "WezTerm"))
+        });
+
+        if iterm {
+            return Ok(Some(Protocol::Iterm));
+        }
+
+        Ok(parse_device_attributes(&report)
+            .is_ok_and(|attributes| sixel::is_supported(&attributes))
+            .then_some(Protocol::Sixel))
+    }
+
+    // Sends `request` followed by a device status report, which every terminal
+    // answers with `ESC [ 0 n`, so that we don't wait for the whole timeout
+    // on terminals ignoring the request.
+    pub(crate) fn query(&mut self, request: &[u8]) -> io::Result<Vec<u8>> {
+        self.query_until([request, b"\x1B[5n"].concat().as_slice(), b"\x1B[0n")
+    }
+
+    // Reads the answer to `request` up to `terminator`, the replies may
+    // contain the last byte of the terminator (e.g. the `n` of `ESC [ 0 n`)
+    // hence we keep reading until the whole sequence has been received.
+    // Only the terminal of the process is able to answer.
+    pub(crate) fn query_until(&mut self, request: &[u8], terminator: &[u8]) -> io::Result<Vec<u8>> {
+        let Some(&last) = terminator.last() else {
+            return Err(io::Error::other("Unable to query: empty terminator"));
+        };
+
+        self.tty()?;
+        self.stdout.flush()?;
+        crate::with_mode(Mode::Raw, || {
+            let mut buf = Vec::new();
+            let deadline = Instant::now() + Duration::from_secs(1);
+
+            self.stderr.write_all(request)?;
+            while !buf.ends_with(terminator) {
+                let timeout = deadline.saturating_duration_since(Instant::now());
+                if self.stdin.read_timeout_until(last, &mut buf, timeout)? == 0 {
+                    break;
+                }
+            }
+
+            Ok(buf)
+        })?
+    }
+
+    fn device(&self) -> io::Result<&(dyn Device + 'a)> {
+        self.device.as_deref().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Unable to retrieve size: no device",
+            )
+        })
+    }
+
+    fn tty(&self) -> io::Result<()> {
+        if self.tty {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Unable to access the terminal: not a terminal",
+            ))
+        }
+    }
+
+    // Writes the capability from the terminfo entry, if any, falling back
+    // to the given (xterm) escape sequence when the terminal doesn't define
+    // it or the entry is malformed.
+    pub(crate) fn write_capability(
+        &mut self,
+        name: &str,
+        params: &[Param],
+        fallback: fmt::Arguments,
+    ) -> io::Result<()> {
+        match self.terminfo.as_ref().and_then(|t| t.expand(name, params)) {
+            Some(Ok(bytes)) => self.stdout.write_all(&bytes),
+            _ => self.stdout.write_fmt(fallback),
+        }
     }
 }
 
-impl<'a> Drop for Term<'a> {
+impl Term<'static, BufWriter<File>, File> {
+    // Opens the controlling terminal, so that the UI can be drawn even when
+    // stdin and stdout are redirected, e.g. `cmd | ourtool | other`.
+    pub fn open_tty() -> io::Result<Self> {
+        match Terminal::load() {
+            Terminal { fd, terms: 1.., .. } if fd != NO_TTY => Self::on_tty(Claim::Shared(fd)),
+            _ => {
+                let tty = File::options().read(true).write(true).open("/dev/tty")?;
+                Self::from_fd(tty.into())
+            }
+        }
+    }
+
+    // A process drives a single terminal at a time, whose file descriptor
+    // is kept open until the last `Term` on it gets dropped so that the
+    // panic hook and the signal handlers can restore it, hence this fails
+    // while a `Term` is open on another one (standard streams included).
+    pub fn from_fd(fd: OwnedFd) -> io::Result<Self> {
+        if unsafe { libc::isatty(fd.as_raw_fd()) } != 1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Self::on_tty(Claim::Owned(fd))
+    }
+
+    fn on_tty(claim: Claim) -> io::Result<Self> {
+        Self::with_io(claim, |fd| {
+            let file = || {
+                unsafe { BorrowedFd::borrow_raw(fd) }
+                    .try_clone_to_owned()
+                    .map(File::from)
+            };
+
+            Ok((nio::from_reader(file()?)?, BufWriter::new(file()?), file()?))
+        })
+    }
+}
+
+impl<'a, W: Write, E: Write> Drop for Term<'a, W, E> {
     fn drop(&mut self) {
-        best_effort(crate::set_mode(Mode::Raw, UpdatePolicy::Now));
+        best_effort(self.set_mode(Mode::Raw));
 
-        best_effort(
-            self.screen()
+        let screen = match self.viewport {
+            Viewport::Fullscreen => self
+                .screen()
                 .set_buffer(Buffer::Alternate)
                 .cursor()
                 .show()
                 .printer()
                 .restore()
                 .screen()
+                .delete_images()
                 .clear()
-                .set_buffer(Buffer::Primary)
-                .flush(),
-        );
+                .set_buffer(Buffer::Primary),
+            // Leave the last frame where it is, moving the cursor below it.
+            Viewport::Inline { height } => {
+                let last_row = self.viewport_origin + [0, height.saturating_sub(1)];
+                self.cursor()
+                    .show()
+                    .set_position(last_row)
+                    .printer()
+                    .restore()
+                    .print("\r\n")
+                    .screen()
+            }
+        };
+
+        best_effort(screen.reset_colors().flush());
+        // Either deleted above or left on purpose with the last frame.
+        if self.tty {
+            restore::set_images_placed(false);
+        }
+
+        best_effort(self.set_mode(Mode::Default));
+        if self.tty {
+            release();
+        }
+    }
+}
 
-        best_effort(crate::set_mode(Mode::Default, UpdatePolicy::Now));
+// Size and mode of the terminal `Term` renders to.
+pub trait Device {
+    fn size(&self) -> io::Result<Vector2<u16>>;
+
+    // Size of the text area in pixels, `[0, 0]` when unknown.
+    fn pixel_size(&self) -> io::Result<Vector2<u16>> {
+        Ok([0, 0].into())
     }
+
+    fn set_mode(&mut self, mode: Mode, policy: UpdatePolicy) -> io::Result<()>;
+}
+
+// The terminal of the process, see `Term::from_fd`.
+#[derive(Default, Debug, Copy, Clone)]
+pub struct Tty;
+
+impl Device for Tty {
+    fn size(&self) -> io::Result<Vector2<u16>> {
+        crate::size()
+    }
+
+    fn pixel_size(&self) -> io::Result<Vector2<u16>> {
+        crate::winsize().map(|win| [win.ws_xpixel, win.ws_ypixel].into())
+    }
+
+    fn set_mode(&mut self, mode: Mode, policy: UpdatePolicy) -> io::Result<()> {
+        crate::set_mode(mode, policy)
+    }
+}
+
+// Colors redefined through `Screen`, to be reset when `Term` gets dropped,
+// and set again when it's resumed.
+#[derive(Default, Debug, Clone)]
+struct Recolored {
+    palette: Vec<(u8, Color)>,
+    foreground: Option<Color>,
+    background: Option<Color>,
+    cursor: Option<Color>,
+}
+
+// Returned by `Term::suspend` once the process has been resumed, the
+// terminal may have been resized in the meantime.
+#[must_use = "the whole screen must be redrawn after resuming"]
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct Resumed {
+    pub size: Vector2<u16>,
 }
 
 // From: (https://en.wikipedia.org/wiki/Terminal_mode)
@@ -123,20 +553,170 @@ pub enum Mode {
     #[default]
     Default,
     Raw,
+    // Input is available a key at a time, without echo, while signals
+    // and output post-processing keep working.
+    Cbreak,
+    Custom(ModeBuilder),
+}
+
+// Settings changed from the default mode, the ones left untouched keep
+// the value they had before `Term::open`.
+#[derive(Default, Debug, PartialEq, Eq, Copy, Clone)]
+pub struct ModeBuilder {
+    echo: Option<bool>,
+    canonical: Option<bool>,
+    signals: Option<bool>,
+    flow_control: Option<bool>,
+    translate_cr: Option<bool>,
+    post_processing: Option<bool>,
+    read_min: Option<u8>,
+    read_timeout: Option<u8>,
+}
+
+impl ModeBuilder {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Input characters are printed back.
+    #[must_use]
+    pub fn with_echo(mut self, enabled: bool) -> Self {
+        self.echo = Some(enabled);
+        self
+    }
+
+    // Input is available a line at a time, after the line editing.
+    #[must_use]
+    pub fn with_canonical(mut self, enabled: bool) -> Self {
+        self.canonical = Some(enabled);
+        self
+    }
+
+    // Ctrl-C, Ctrl-\ and Ctrl-Z raise SIGINT, SIGQUIT and SIGTSTP.
+    #[must_use]
+    pub fn with_signals(mut self, enabled: bool) -> Self {
+        self.signals = Some(enabled);
+        self
+    }
+
+    // Ctrl-S and Ctrl-Q stop and resume the output.
+    #[must_use]
+    pub fn with_flow_control(mut self, enabled: bool) -> Self {
+        self.flow_control = Some(enabled);
+        self
+    }
+
+    // Carriage returns are read as new lines.
+    #[must_use]
+    pub fn with_translate_cr(mut self, enabled: bool) -> Self {
+        self.translate_cr = Some(enabled);
+        self
+    }
+
+    // Output is processed, e.g. new lines are written as `\r\n`.
+    #[must_use]
+    pub fn with_post_processing(mut self, enabled: bool) -> Self {
+        self.post_processing = Some(enabled);
+        self
+    }
+
+    // From: (https://man7.org/linux/man-pages/man3/termios.3.html)
+    // VMIN, the minimum number of bytes returned by non canonical reads.
+    #[must_use]
+    pub fn with_read_min(mut self, bytes: u8) -> Self {
+        self.read_min = Some(bytes);
+        self
+    }
+
+    // VTIME, how long non canonical reads wait for input, in tenths of a
+    // second, rounded down and capped to 25.5 seconds.
+    #[must_use]
+    pub fn with_read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(u8::try_from(timeout.as_millis() / 100).unwrap_or(u8::MAX));
+        self
+    }
+
+    #[must_use]
+    pub fn build(self) -> Mode {
+        Mode::Custom(self)
+    }
+
+    #[must_use]
+    fn apply(self, state: State) -> State {
+        let State(mut inner) = state;
+        let set = |flags: &mut tcflag_t, flag: tcflag_t, enabled: Option<bool>| match enabled {
+            Some(true) => *flags |= flag,
+            Some(false) => *flags &= !flag,
+            None => {}
+        };
+
+        set(&mut inner.c_lflag, ECHO, self.echo);
+        set(&mut inner.c_lflag, ICANON, self.canonical);
+        set(&mut inner.c_lflag, ISIG, self.signals);
+        set(&mut inner.c_iflag, IXON, self.flow_control);
+        set(&mut inner.c_iflag, ICRNL, self.translate_cr);
+        set(&mut inner.c_oflag, OPOST, self.post_processing);
+
+        if let Some(bytes) = self.read_min {
+            inner.c_cc[VMIN] = bytes;
+        }
+        if let Some(tenths) = self.read_timeout {
+            inner.c_cc[VTIME] = tenths;
+        }
+
+        State(inner)
+    }
 }
 
 pub fn with_mode<T, F>(mode: Mode, f: F) -> io::Result<T>
 where
     F: FnOnce() -> T,
 {
-    let state = crate::state()?;
-    crate::set_mode(mode, UpdatePolicy::Now)?;
+    let guard = ModeGuard::enter(mode)?;
     let out = f();
-    crate::restore(&state, UpdatePolicy::Now)?;
+    guard.leave()?;
     Ok(out)
 }
 
+// Restores the mode that was in effect before `Term::enter` once dropped,
+// guards are meant to be dropped in reverse order, as scopes do.
+#[must_use = "the previous mode is restored as soon as the guard gets dropped"]
+pub struct ModeGuard {
+    previous: Option<State>,
+}
+
+impl ModeGuard {
+    fn enter(mode: Mode) -> io::Result<Self> {
+        let previous = crate::state()?;
+        crate::set_mode(mode, UpdatePolicy::Now)?;
+        Ok(Self {
+            previous: Some(previous),
+        })
+    }
+
+    // Like dropping the guard, but reporting failures.
+    pub fn leave(mut self) -> io::Result<()> {
+        match self.previous.take() {
+            Some(state) => crate::restore(&state, UpdatePolicy::Now),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for ModeGuard {
+    fn drop(&mut self) {
+        if let Some(state) = self.previous.take() {
+            best_effort(crate::restore(&state, UpdatePolicy::Now));
+        }
+    }
+}
+
 pub fn size() -> io::Result<Vector2<u16>> {
+    crate::winsize().map(|win| [win.ws_col, win.ws_row].into())
+}
+
+fn winsize() -> io::Result<winsize> {
     let mut win = winsize {
         ws_row: 0,
         ws_col: 0,
@@ -144,10 +724,57 @@ pub fn size() -> io::Result<Vector2<u16>> {
         ws_ypixel: 0,
     };
 
-    if unsafe { ioctl(stdin_fileno(), TIOCGWINSZ, &mut win as *mut _) } == -1 {
+    if unsafe { ioctl(tty_fileno(), TIOCGWINSZ, &mut win as *mut _) } == -1 {
         Err(io::Error::last_os_error())
     } else {
-        Ok([win.ws_col, win.ws_row].into())
+        Ok(win)
+    }
+}
+
+fn parse_device_attributes(report: &[u8]) -> io::Result<Vec<u16>> {
+    let start = report
+        .windows(3)
+        .position(|w| w == b"\x1B[?")
+        .map(|i| i + 3)
+        .ok_or_else(|| io::Error::other("Unable to retrieve attributes: missing report"))?;
+
+    let len = report[start..]
+        .iter()
+        .position(|b| *b == b'c')
+        .ok_or_else(|| io::Error::other("Unable to retrieve attributes: missing token `c`"))?;
+
+    std::str::from_utf8(&report[start..start + len])
+        .map_err(io::Error::other)?
+        .split(';')
+        .map(|attribute| attribute.parse().map_err(io::Error::other))
+        .collect()
+}
+
+// From: (https://invisible-island.net/xterm/ctlseqs/ctlseqs.html#h3-Functions-using-CSI-_-ordered-by-the-final-character_s_)
+// >>> Ps = 1 4  -> Report xterm text area size in pixels.
+// >>>   Result is CSI  4 ;  height ;  width t
+// >>> Ps = 1 6  -> Report xterm character cell size in pixels.
+// >>>   Result is CSI  6 ;  height ;  width t
+fn parse_window_report(bytes: &[u8], kind: u8) -> io::Result<Vector2<u16>> {
+    let head = format!("\x1B[{};", kind);
+    let start = bytes
+        .windows(head.len())
+        .position(|w| w == head.as_bytes())
+        .map(|i| i + head.len())
+        .ok_or_else(|| io::Error::other("Unable to retrieve size: missing report"))?;
+
+    let report = bytes[start..]
+        .split(|b| *b == b't')
+        .next()
+        .and_then(|b| std::str::from_utf8(b).ok())
+        .ok_or_else(|| io::Error::other("Unable to retrieve size: missing token `t`"))?;
+
+    let mut fields = report.split(';').map(str::parse::<u16>);
+    match (fields.next(), fields.next(), fields.next()) {
+        (Some(Ok(height)), Some(Ok(width)), None) if height > 0 && width > 0 => {
+            Ok([width, height].into())
+        }
+        _ => Err(io::Error::other("Unable to retrieve size: bad report")),
     }
 }
 
@@ -159,11 +786,24 @@ fn set_mode(mode: Mode, policy: UpdatePolicy) -> io::Result<()> {
     match mode {
         Mode::Default => crate::restore(&state, policy),
         Mode::Raw => crate::restore(&state.make_raw(), policy),
+        Mode::Cbreak => crate::restore(&CBREAK.apply(state), policy),
+        Mode::Custom(builder) => crate::restore(&builder.apply(state), policy),
     }
 }
 
+const CBREAK: ModeBuilder = ModeBuilder {
+    echo: Some(false),
+    canonical: Some(false),
+    signals: None,
+    flow_control: None,
+    translate_cr: None,
+    post_processing: None,
+    read_min: Some(1),
+    read_timeout: Some(0),
+};
+
+// State of the terminal before the first `Term` claiming it, see `claim`.
 static mut DEFAULT_STATE: MaybeUninit<State> = MaybeUninit::uninit();
-static INIT: Once = Once::new();
 
 #[derive(Copy, Clone)]
 struct State(Termios);
@@ -177,39 +817,34 @@ impl State {
     }
 }
 
+// The current state stands for the default one while it's being saved,
+// or when no `Term` is open.
 fn default_state() -> io::Result<State> {
-    if !INIT.is_completed() {
-        // If we are here does not mean `INIT` has not been executed,
-        // `is_completed` may have returned a stale value.
-        // If that's the case, someone else may have initialized `DEFAULT_STATE`
-        // to the proper value and we must preserve it, therefore if `crate::state()`
-        // returns an Ok, it will be discarded because the call to `call_once_force`
-        // won't run (since someone else alredy initialized `DEFAULT_STATE`).
-        // If otherwise `crate::state()` returns an Err, the latter will be returned,
-        // even if `DEFAULT_STATE` has been properly initialized by someone else.
-        // This is not good actually, anyway subsequent calls to this function
-        // will return the right value, by now this function is best-effort.
-        // If instead no one else initialized `DEFAULT_STATE` and `crate::state()`
-        // returned successfully we are the one that are going to initialize `DEFAULT_STATE`.
-        let state = crate::state()?;
-        INIT.call_once_force(|_| {
-            unsafe { DEFAULT_STATE.write(state) };
-        })
+    match saved_state() {
+        Some(state) => Ok(state),
+        None => crate::state(),
     }
+}
 
-    Ok(unsafe { DEFAULT_STATE.assume_init() })
+fn saved_state() -> Option<State> {
+    Terminal::load()
+        .saved
+        .then(|| unsafe { DEFAULT_STATE.assume_init() })
 }
 
 fn restore(state: &State, policy: UpdatePolicy) -> io::Result<()> {
-    tcsetattr(stdin_fileno(), policy.action(), &state.0)
+    tcsetattr(tty_fileno(), policy.action(), &state.0)
 }
 
 fn state() -> io::Result<State> {
-    Termios::from_fd(stdin_fileno()).map(State)
+    Termios::from_fd(tty_fileno()).map(State)
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
-enum UpdatePolicy {
+// When a mode change takes effect, `Lazy` waits for the pending output
+// to be written and discards the pending input.
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum UpdatePolicy {
+    #[default]
     Now,
     Lazy,
 }
@@ -223,10 +858,438 @@ impl UpdatePolicy {
     }
 }
 
+// The terminal driven by the `Term`s of the process, packed in a single
+// atomic so that it's claimed and released at once, and can be read from
+// signal handlers.
+static TERMINAL: AtomicU64 = AtomicU64::new(Terminal::FREE.pack());
+const NO_TTY: RawFd = -1;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct Terminal {
+    // Opened through `Term::from_fd`, `NO_TTY` for the standard streams.
+    fd: RawFd,
+    // `DEFAULT_STATE` holds the state of `fd`.
+    saved: bool,
+    terms: u32,
+}
+
+// How `Term` gets hold of the terminal.
+enum Claim {
+    Standard,
+    // Closed once released by the last `Term`.
+    Owned(OwnedFd),
+    // Already claimed by another `Term`.
+    Shared(RawFd),
+}
+
+impl Terminal {
+    const FREE: Self = Self {
+        fd: NO_TTY,
+        saved: false,
+        terms: 0,
+    };
+    const SAVED: u64 = 1 << 31;
+
+    #[must_use]
+    const fn pack(self) -> u64 {
+        (self.fd as u32 as u64) << 32 | if self.saved { Self::SAVED } else { 0 } | self.terms as u64
+    }
+
+    #[must_use]
+    const fn unpack(packed: u64) -> Self {
+        Self {
+            fd: (packed >> 32) as u32 as RawFd,
+            saved: packed & Self::SAVED != 0,
+            terms: (packed & (Self::SAVED - 1)) as u32,
+        }
+    }
+
+    #[must_use]
+    fn load() -> Self {
+        Self::unpack(TERMINAL.load(Ordering::SeqCst))
+    }
+}
+
+// Claims the terminal for a new `Term`, saving its state if it's the first
+// one, returns the file descriptor to use.
+fn claim(claim: Claim) -> io::Result<RawFd> {
+    let fd = match &claim {
+        Claim::Standard => NO_TTY,
+        Claim::Owned(fd) => fd.as_raw_fd(),
+        Claim::Shared(fd) => *fd,
+    };
+
+    let previous = TERMINAL
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |packed| {
+            let terminal = Terminal::unpack(packed);
+            match (&claim, terminal.terms) {
+                (Claim::Standard | Claim::Owned(_), 0) => Some(Terminal {
+                    fd,
+                    saved: false,
+                    terms: 1,
+                }),
+                (Claim::Standard | Claim::Shared(_), 1..) if terminal.fd == fd => Some(Terminal {
+                    terms: terminal.terms + 1,
+                    ..terminal
+                }),
+                _ => None,
+            }
+            .map(Terminal::pack)
+        })
+        .map_err(|_| {
+            io::Error::other("Unable to open terminal: another terminal is already in use")
+        })?;
+
+    // Closed by `release` from now on.
+    if let Claim::Owned(fd) = claim {
+        let _ = fd.into_raw_fd();
+    }
+
+    if Terminal::unpack(previous).terms == 0 {
+        match crate::state() {
+            Ok(state) => {
+                unsafe { (*ptr::addr_of_mut!(DEFAULT_STATE)).write(state) };
+                TERMINAL.fetch_or(Terminal::SAVED, Ordering::SeqCst);
+            }
+            Err(err) => {
+                release();
+                return Err(err);
+            }
+        }
+    }
+
+    Ok(fd)
+}
+
+// Releases the terminal claimed by a `Term`, the last one closes the file
+// descriptor given to `Term::from_fd`.
+fn release() {
+    let previous = TERMINAL.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |packed| {
+        let terminal = Terminal::unpack(packed);
+        match terminal.terms {
+            0 => None,
+            1 => Some(Terminal::FREE),
+            terms => Some(Terminal {
+                terms: terms - 1,
+                ..terminal
+            }),
+        }
+        .map(Terminal::pack)
+    });
+
+    if let Ok(previous) = previous.map(Terminal::unpack) {
+        if previous.terms == 1 && previous.fd != NO_TTY {
+            drop(unsafe { OwnedFd::from_raw_fd(previous.fd) });
+        }
+    }
+}
+
+// Terminal input, where the termios settings are applied. Like
+// `output_fileno`, this is async-signal-safe: it doesn't go through the
+// standard handles, whose first use allocates and takes locks.
 #[must_use]
-fn stdin_fileno() -> RawFd {
-    let stdin = io::stdin();
-    stdin.as_raw_fd()
+fn tty_fileno() -> RawFd {
+    match Terminal::load().fd {
+        NO_TTY => libc::STDIN_FILENO,
+        fd => fd,
+    }
+}
+
+// Terminal output, where the panic hook and signal handlers write to.
+#[must_use]
+fn output_fileno() -> RawFd {
+    match Terminal::load().fd {
+        NO_TTY => libc::STDOUT_FILENO,
+        fd => fd,
+    }
 }
 
 fn best_effort<T, E>(_: Result<T, E>) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing::pty::Pty;
+
+    use std::cell::RefCell;
+    use std::fs;
+    use std::process::Command;
+    use std::rc::Rc;
+
+    const CHILD_ENV: &str = "TERMULAR_PTY_CHILD";
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    // Runs `test` on a pty, where the virtual terminal answers the queries.
+    fn spawn_child(test: &str, size: [u16; 2]) -> Pty {
+        let mut command = Command::new(env::current_exe().unwrap());
+        command
+            .args([test, "--exact", "--nocapture"])
+            .env(CHILD_ENV, "1");
+        Pty::spawn(command, size).unwrap()
+    }
+
+    // Records the modes being set.
+    struct Fixed(Vector2<u16>, Rc<RefCell<Vec<(Mode, UpdatePolicy)>>>);
+
+    impl Device for Fixed {
+        fn size(&self) -> io::Result<Vector2<u16>> {
+            Ok(self.0)
+        }
+
+        fn set_mode(&mut self, mode: Mode, policy: UpdatePolicy) -> io::Result<()> {
+            self.1.borrow_mut().push((mode, policy));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn window_reports() {
+        let sut = parse_window_report;
+
+        assert_eq!(sut(b"\x1B[4;600;800t", 4).unwrap(), [800, 600].into());
+        assert_eq!(sut(b"\x1B[6;16;8t\x1B[0n", 6).unwrap(), [8, 16].into());
+
+        assert!(sut(b"\x1B[4;600;800t", 6).is_err());
+        assert!(sut(b"\x1B[0n", 4).is_err());
+        assert!(sut(b"\x1B[4;600", 4).is_err());
+        assert!(sut(b"\x1B[4;600t", 4).is_err());
+        assert!(sut(b"\x1B[4;600;800;1t", 4).is_err());
+        assert!(sut(b"\x1B[4;a;800t", 4).is_err());
+        assert!(sut(b"\x1B[4;-1;800t", 4).is_err());
+        assert!(sut(b"\x1B[4;0;800t", 4).is_err());
+    }
+
+    #[test]
+    fn modes() {
+        let mut inner: Termios = unsafe { std::mem::zeroed() };
+        inner.c_lflag = ECHO | ICANON | ISIG;
+        inner.c_iflag = IXON | ICRNL;
+        inner.c_oflag = OPOST;
+        inner.c_cc[VMIN] = 0;
+        inner.c_cc[VTIME] = 5;
+        let state = State(inner);
+
+        let State(cbreak) = CBREAK.apply(state);
+        assert_eq!(cbreak.c_lflag, ISIG);
+        assert_eq!(cbreak.c_iflag, IXON | ICRNL);
+        assert_eq!(cbreak.c_oflag, OPOST);
+        assert_eq!((cbreak.c_cc[VMIN], cbreak.c_cc[VTIME]), (1, 0));
+
+        let builder = ModeBuilder::new()
+            .with_signals(false)
+            .with_flow_control(false)
+            .with_post_processing(false)
+            .with_read_timeout(Duration::from_millis(1250));
+        assert_eq!(builder.build(), Mode::Custom(builder));
+        let State(custom) = builder.apply(state);
+        assert_eq!(custom.c_lflag, ECHO | ICANON);
+        assert_eq!(custom.c_iflag, ICRNL);
+        assert_eq!(custom.c_oflag, 0);
+        assert_eq!((custom.c_cc[VMIN], custom.c_cc[VTIME]), (0, 12));
+
+        let State(default) = ModeBuilder::new().apply(state);
+        assert!(default == inner);
+
+        let State(enabled) = ModeBuilder::new()
+            .with_echo(true)
+            .with_read_min(4)
+            .with_read_timeout(Duration::from_secs(60))
+            .apply(State(cbreak));
+        assert_eq!(enabled.c_lflag, ECHO | ISIG);
+        assert_eq!((enabled.c_cc[VMIN], enabled.c_cc[VTIME]), (4, u8::MAX));
+    }
+
+    // Runs in the child spawned by `queries`, does nothing otherwise.
+    #[test]
+    fn queries_child() {
+        if env::var_os(CHILD_ENV).is_none() {
+            return;
+        }
+
+        let (stdout, stderr) = (io::stdout(), io::stderr());
+        let mut term = Term::open(stdout.lock(), stderr.lock()).unwrap();
+        term.cursor()
+            .set_position([4, 1])
+            .printer()
+            .print("ready")
+            .flush()
+            .unwrap();
+
+        let position = term.cursor().position().unwrap();
+        let attributes = term.device_attributes().unwrap();
+        let colors = term.query_colors().unwrap();
+        term.printer()
+            .print(format!(
+                " at {} {:?} {:?}",
+                position,
+                attributes,
+                [colors.foreground, colors.background]
+            ))
+            .flush()
+            .unwrap();
+    }
+
+    #[test]
+    fn queries() {
+        let mut pty = spawn_child("tests::queries_child", [60, 10]);
+        assert!(pty.wait(TIMEOUT).unwrap().success());
+        assert!(pty
+            .vt()
+            .text()
+            .contains("ready at [9, 1] [1, 2] [Default, Default]"));
+    }
+
+    #[test]
+    fn claims_child() {
+        if env::var_os(CHILD_ENV).is_none() {
+            return;
+        }
+
+        let tty = || {
+            OwnedFd::from(
+                File::options()
+                    .read(true)
+                    .write(true)
+                    .open("/dev/tty")
+                    .unwrap(),
+            )
+        };
+        let threads = || fs::read_dir("/proc/self/task").unwrap().count();
+        let standard = || {
+            let (stdout, stderr) = (io::stdout(), io::stderr());
+            Term::open(stdout.lock(), stderr.lock()).map(drop)
+        };
+
+        let before = threads();
+        for _ in 0..2 {
+            let term = Term::from_fd(tty()).unwrap();
+            let shared = Term::open_tty().unwrap();
+            assert!(Term::from_fd(tty()).is_err());
+            assert!(standard().is_err());
+            drop((term, shared));
+            assert_eq!(threads(), before);
+        }
+
+        let (stdout, stderr) = (io::stdout(), io::stderr());
+        let term = Term::open(stdout.lock(), stderr.lock()).unwrap();
+        assert!(Term::open_tty().is_err());
+        drop(term);
+        assert!(Term::open_tty().is_ok());
+        assert_eq!(threads(), before);
+        println!("claims ok");
+    }
+
+    #[test]
+    fn claims() {
+        let mut pty = spawn_child("tests::claims_child", [80, 10]);
+        assert!(pty.wait(TIMEOUT).unwrap().success());
+        assert!(pty.vt().text().contains("claims ok"));
+
+        let terminal = Terminal {
+            fd: 7,
+            saved: true,
+            terms: 3,
+        };
+        assert_eq!(Terminal::unpack(terminal.pack()), terminal);
+        assert_eq!(Terminal::unpack(Terminal::FREE.pack()), Terminal::FREE);
+    }
+
+    #[test]
+    fn suspend_child() {
+        if env::var_os(CHILD_ENV).is_none() {
+            return;
+        }
+
+        let (stdout, stderr) = (io::stdout(), io::stderr());
+        let mut term = Term::open(stdout.lock(), stderr.lock()).unwrap();
+        term.set_mode(Mode::Raw).unwrap();
+        term.screen()
+            .set_buffer(Buffer::Alternate)
+            .set_default_background(Color::Rgb(1, 2, 3))
+            .cursor()
+            .hide()
+            .printer()
+            .print("suspending")
+            .flush()
+            .unwrap();
+
+        let resumed = term.suspend().unwrap();
+        term.printer()
+            .print(format_args!("resumed on {}", resumed.size))
+            .flush()
+            .unwrap();
+
+        let mut key = [0];
+        term.stdin_mut().read_timeout(&mut key, TIMEOUT).unwrap();
+    }
+
+    #[test]
+    fn suspend() {
+        // The shell stops the child as its foreground job, then waits for a
+        // line before resuming it.
+        let mut command = Command::new("bash");
+        command
+            .args([
+                "-c",
+                r#"set -m; "$0" tests::suspend_child --exact --nocapture; echo "stopped with $?"; read; fg"#,
+            ])
+            .arg(env::current_exe().unwrap())
+            .env(CHILD_ENV, "1");
+        let mut pty = Pty::spawn(command, [60, 10]).unwrap();
+
+        pty.wait_until(TIMEOUT, |vt| vt.text().contains("stopped with 148"))
+            .unwrap();
+        assert!(!pty.vt().alternate_buffer());
+        assert!(pty.vt().cursor_visible());
+        assert_ne!(pty.termios().unwrap().c_lflag & ICANON, 0);
+
+        pty.send(b"\n").unwrap();
+        pty.wait_until(TIMEOUT, |vt| vt.text().contains("resumed on [60, 10]"))
+            .unwrap();
+        assert!(pty.vt().alternate_buffer());
+        assert!(!pty.vt().cursor_visible());
+        assert_eq!(pty.termios().unwrap().c_lflag & ICANON, 0);
+
+        pty.send(b"q").unwrap();
+        assert!(pty.wait(TIMEOUT).unwrap().success());
+    }
+
+    #[test]
+    fn writer() {
+        let mut term = Term::with_writer(Vec::new());
+        term.cursor()
+            .set_position([1, 2])
+            .printer()
+            .print("hi")
+            .flush()
+            .unwrap();
+
+        assert_eq!(term.stdout(), b"\x1B[3;2Hhi");
+        assert!(term.size().is_err());
+        assert!(term.cursor().position().is_err());
+        let mut key = [0];
+        assert_eq!(
+            term.stdin_mut()
+                .read_timeout(&mut key, Duration::ZERO)
+                .unwrap(),
+            0
+        );
+        let _: &io::Sink = term.stderr();
+
+        let modes = Rc::default();
+        let mut term = term.with_device(Fixed([80, 24].into(), Rc::clone(&modes)));
+        assert_eq!(term.size().unwrap(), [80, 24].into());
+        assert_eq!(term.viewport_size().unwrap(), [80, 24].into());
+
+        term.set_mode(Mode::Raw).unwrap();
+        term.flow().set_mode(Mode::Cbreak).flush().unwrap();
+        assert_eq!(
+            *modes.borrow(),
+            [
+                (Mode::Raw, UpdatePolicy::Now),
+                (Mode::Cbreak, UpdatePolicy::Lazy)
+            ]
+        );
+    }
+}
@@ -1,16 +1,39 @@
+use crate::best_effort;
+
 use std::io::{self, BufRead, BufReader, IoSliceMut, Read};
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd};
 use std::sync::mpsc::{self, Receiver};
+use std::thread::{self, JoinHandle};
 use std::time::{Duration, Instant};
-use std::{iter, mem, thread};
+use std::{iter, mem};
+
+use libc::{pollfd, POLLIN};
 
 pub struct Stdin {
     inner: BufReader<StdinRaw>,
 }
 
+pub(crate) fn stdin() -> io::Result<Stdin> {
+    from_reader(io::stdin())
+}
+
+// Reads the input of the terminal from any source, e.g. `/dev/tty`.
+pub(crate) fn from_reader<R: Read + AsRawFd + Send + 'static>(reader: R) -> io::Result<Stdin> {
+    Ok(Stdin {
+        inner: BufReader::new(StdinRaw::new(reader)?),
+    })
+}
+
+// Input that is never available, without spawning any reader thread.
 #[must_use]
-pub(crate) fn stdin() -> Stdin {
+pub(crate) fn empty() -> Stdin {
+    let (_, receiver) = mpsc::channel();
     Stdin {
-        inner: BufReader::new(StdinRaw::new()),
+        inner: BufReader::new(StdinRaw {
+            receiver,
+            last_err: Ok(()),
+            reader: None,
+        }),
     }
 }
 
@@ -98,33 +121,77 @@ const READ_TIMEOUT: Duration = Duration::from_millis(8);
 struct StdinRaw {
     receiver: Receiver<io::Result<Option<u8>>>,
     last_err: io::Result<()>,
+    // The write end of a pipe, closed to stop the reader thread.
+    reader: Option<(OwnedFd, JoinHandle<()>)>,
 }
 
 impl StdinRaw {
-    #[must_use]
-    fn new() -> StdinRaw {
+    fn new<R: Read + AsRawFd + Send + 'static>(mut reader: R) -> io::Result<StdinRaw> {
         let (sender, receiver) = mpsc::channel();
         let last_err = Ok(());
 
-        thread::spawn(move || {
-            let stdin = io::stdin();
-            let handle = stdin.lock();
-            let mut stream = handle.bytes();
+        let mut pipe = [0; 2];
+        if unsafe { libc::pipe2(pipe.as_mut_ptr(), libc::O_CLOEXEC) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        let (stop, stopper) =
+            unsafe { (OwnedFd::from_raw_fd(pipe[0]), OwnedFd::from_raw_fd(pipe[1])) };
+
+        let thread = thread::spawn(move || {
+            // Large enough for `io::stdin()` to bypass its own buffer, which
+            // `poll` can't see.
+            let mut buf = [0; 8192];
+            let mut fds = [reader.as_raw_fd(), stop.as_raw_fd()].map(|fd| pollfd {
+                fd,
+                events: POLLIN,
+                revents: 0,
+            });
 
             // From: (https://doc.rust-lang.org/std/sync/mpsc/struct.SendError.html)
             // >>> A send operation can only fail if the receiving end of a channel
             // >>> is disconnected, implying that the data could never be received.
             //
-            // loop until the receiving end of the channel is disconnected.
-            // EOF is transmitted as Ok(None).
-            while stream.try_for_each(|io| sender.send(io.map(Some))).is_ok() {
-                if sender.send(Ok(None)).is_err() {
+            // loop until the receiving end of the channel is disconnected,
+            // or until stopped. EOF is transmitted as Ok(None).
+            loop {
+                if unsafe { libc::poll(fds.as_mut_ptr(), 2, -1) } == -1 {
+                    let err = io::Error::last_os_error();
+                    if err.kind() == io::ErrorKind::Interrupted {
+                        continue;
+                    }
+                    best_effort(sender.send(Err(err)));
+                    break;
+                }
+                if fds[1].revents != 0 {
+                    break;
+                }
+
+                let sent = match reader.read(&mut buf) {
+                    Ok(0) => sender.send(Ok(None)),
+                    Ok(n) => buf[..n].iter().try_for_each(|b| sender.send(Ok(Some(*b)))),
+                    Err(err) if err.kind() == io::ErrorKind::Interrupted => Ok(()),
+                    Err(err) => sender.send(Err(err)),
+                };
+                if sent.is_err() {
                     break;
                 }
             }
         });
 
-        StdinRaw { receiver, last_err }
+        Ok(StdinRaw {
+            receiver,
+            last_err,
+            reader: Some((stopper, thread)),
+        })
+    }
+}
+
+impl Drop for StdinRaw {
+    fn drop(&mut self) {
+        if let Some((stopper, thread)) = self.reader.take() {
+            drop(stopper);
+            best_effort(thread.join());
+        }
     }
 }
 
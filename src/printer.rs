@@ -1,26 +1,33 @@
-use crate::cursor::Cursor;
+use crate::canvas::Canvas;
+use crate::cursor::{self, Cursor};
 use crate::flow::Flow;
 use crate::screen::Screen;
+use crate::text::Text;
+use crate::vector::Vector2;
 use crate::Term;
 
+use std::borrow::Cow;
 use std::fmt::{self, Debug, Display, Formatter};
-use std::io::{self, Write};
+use std::io::{self, StderrLock, StdoutLock, Write};
+use std::str;
 
-pub struct Printer<'a: 'b, 'b>(pub(crate) io::Result<&'b mut Term<'a>>);
+pub struct Printer<'a: 'b, 'b, W: Write = StdoutLock<'a>, E: Write = StderrLock<'a>>(
+    pub(crate) io::Result<&'b mut Term<'a, W, E>>,
+);
 
-impl<'a, 'b> Printer<'a, 'b> {
+impl<'a, 'b, W: Write, E: Write> Printer<'a, 'b, W, E> {
     #[must_use]
-    pub fn cursor(self) -> Cursor<'a, 'b> {
+    pub fn cursor(self) -> Cursor<'a, 'b, W, E> {
         Cursor(self.0)
     }
 
     #[must_use]
-    pub fn screen(self) -> Screen<'a, 'b> {
+    pub fn screen(self) -> Screen<'a, 'b, W, E> {
         Screen(self.0)
     }
 
     #[must_use]
-    pub fn flow(self) -> Flow<'a, 'b> {
+    pub fn flow(self) -> Flow<'a, 'b, W, E> {
         Flow(self.0)
     }
 
@@ -34,9 +41,36 @@ impl<'a, 'b> Printer<'a, 'b> {
         self.chain(|t| write!(t.stdout_mut(), "{:?}", s))
     }
 
+    // Prints the canvas rows one below the other, starting at `position`.
+    #[must_use]
+    pub fn print_canvas(self, canvas: &Canvas, position: impl Into<Vector2<u16>>) -> Self {
+        let position = position.into();
+        self.chain(|t| {
+            canvas.rows().zip(0..).try_for_each(|(row, y)| {
+                cursor::write_position(t, position + [0, y])?;
+                write!(t.stdout_mut(), "{}", row)
+            })
+        })
+    }
+
+    // Prints the text lines one below the other, starting at `position`.
+    // Lines below the last addressable row are not printed.
+    #[must_use]
+    pub fn print_text(self, text: &Text, position: impl Into<Vector2<u16>>) -> Self {
+        let position = position.into();
+        self.chain(|t| {
+            text.styled_lines()
+                .zip(position[1]..=u16::MAX)
+                .try_for_each(|(line, y)| {
+                    cursor::write_position(t, [position[0], y].into())?;
+                    write!(t.stdout_mut(), "{}", line)
+                })
+        })
+    }
+
     #[must_use]
     pub fn restore(self) -> Self {
-        self.chain(|t| write!(t.stdout_mut(), "\x1B[{}m", fmt_restore()))
+        self.chain(|t| t.write_capability("sgr0", &[], format_args!("\x1B[{}m", fmt_restore())))
     }
 
     pub fn flush(self) -> io::Result<()> {
@@ -47,7 +81,7 @@ impl<'a, 'b> Printer<'a, 'b> {
     #[must_use]
     fn chain<F>(self, f: F) -> Self
     where
-        F: FnOnce(&mut Term) -> io::Result<()>,
+        F: FnOnce(&mut Term<'a, W, E>) -> io::Result<()>,
     {
         Self(self.0.and_then(|t| f(t).map(|_| t)))
     }
@@ -65,6 +99,68 @@ pub enum Color {
     Magenta,
     Cyan,
     White,
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    // From: (https://www.w3.org/TR/WCAG21/#dfn-relative-luminance)
+    #[must_use]
+    pub fn luminance(self) -> Option<f64> {
+        let linear = |c: u8| {
+            let c = f64::from(c) / 255.0;
+            if c <= 0.03928 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        };
+
+        match self {
+            Self::Rgb(r, g, b) => {
+                Some(0.2126 * linear(r) + 0.7152 * linear(g) + 0.0722 * linear(b))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Theme {
+    Dark,
+    Light,
+}
+
+// From: (https://www.w3.org/TR/WCAG21/#dfn-contrast-ratio)
+// the luminance contrasting as much with black as with white, solving
+// (L + 0.05) / 0.05 = 1.05 / (L + 0.05).
+const MID_LUMINANCE: f64 = 0.179_129;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Colors {
+    pub foreground: Color,
+    pub background: Color,
+}
+
+impl Colors {
+    // Falls back on the foreground, being the opposite of the background,
+    // when the latter is not known.
+    #[must_use]
+    pub fn theme(&self) -> Option<Theme> {
+        let theme = |l: f64| {
+            if l < MID_LUMINANCE {
+                Theme::Dark
+            } else {
+                Theme::Light
+            }
+        };
+
+        self.background.luminance().map(theme).or_else(|| {
+            self.foreground.luminance().map(|l| match theme(l) {
+                Theme::Dark => Theme::Light,
+                Theme::Light => Theme::Dark,
+            })
+        })
+    }
 }
 
 #[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
@@ -83,7 +179,7 @@ pub enum FontWeight {
     Bold,
 }
 
-#[derive(Default, Copy, Clone, PartialEq, Eq)]
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
 pub struct Style {
     pub foreground: Color,
     pub background: Color,
@@ -126,7 +222,7 @@ impl Style {
     #[must_use]
     pub fn background(color: Color) -> Self {
         Style {
-            foreground: color,
+            background: color,
             ..Default::default()
         }
     }
@@ -154,8 +250,30 @@ impl Style {
         self.weight = weight;
         self
     }
+
+    // Overrides the attributes that are set in `other`, default attributes
+    // are inherited from `self`.
+    #[must_use]
+    pub fn patch(self, other: Style) -> Self {
+        Self {
+            foreground: patch(self.foreground, other.foreground),
+            background: patch(self.background, other.background),
+            decoration: patch(self.decoration, other.decoration),
+            weight: patch(self.weight, other.weight),
+        }
+    }
+}
+
+#[must_use]
+fn patch<T: Default + PartialEq>(this: T, other: T) -> T {
+    if other == T::default() {
+        this
+    } else {
+        other
+    }
 }
 
+#[derive(Default, Clone, PartialEq, Eq)]
 pub struct StyledContent<T> {
     pub style: Style,
     pub content: T,
@@ -294,32 +412,52 @@ fn fmt_decoration(decoration: TextDecoration) -> &'static str {
 }
 
 #[must_use]
-fn fmt_foreground(color: Color) -> &'static str {
+fn fmt_foreground(color: Color) -> Cow<'static, str> {
     match color {
-        Color::Default => "39",
-        Color::Black => "30",
-        Color::Red => "31",
-        Color::Green => "32",
-        Color::Yellow => "33",
-        Color::Blue => "34",
-        Color::Magenta => "35",
-        Color::Cyan => "36",
-        Color::White => "37",
+        Color::Default => "39".into(),
+        Color::Black => "30".into(),
+        Color::Red => "31".into(),
+        Color::Green => "32".into(),
+        Color::Yellow => "33".into(),
+        Color::Blue => "34".into(),
+        Color::Magenta => "35".into(),
+        Color::Cyan => "36".into(),
+        Color::White => "37".into(),
+        Color::Rgb(r, g, b) => format!("38;2;{};{};{}", r, g, b).into(),
     }
 }
 
 #[must_use]
-fn fmt_background(color: Color) -> &'static str {
+fn fmt_background(color: Color) -> Cow<'static, str> {
     match color {
-        Color::Default => "49",
-        Color::Black => "40",
-        Color::Red => "41",
-        Color::Green => "42",
-        Color::Yellow => "43",
-        Color::Blue => "44",
-        Color::Magenta => "45",
-        Color::Cyan => "46",
-        Color::White => "47",
+        Color::Default => "49".into(),
+        Color::Black => "40".into(),
+        Color::Red => "41".into(),
+        Color::Green => "42".into(),
+        Color::Yellow => "43".into(),
+        Color::Blue => "44".into(),
+        Color::Magenta => "45".into(),
+        Color::Cyan => "46".into(),
+        Color::White => "47".into(),
+        Color::Rgb(r, g, b) => format!("48;2;{};{};{}", r, g, b).into(),
+    }
+}
+
+// From: (https://invisible-island.net/xterm/ctlseqs/ctlseqs.html#h3-Operating-System-Commands)
+// `None` stands for the terminal default color, which can only be reset.
+#[must_use]
+pub(crate) fn fmt_color_spec(color: Color) -> Option<Cow<'static, str>> {
+    match color {
+        Color::Default => None,
+        Color::Black => Some("black".into()),
+        Color::Red => Some("red".into()),
+        Color::Green => Some("green".into()),
+        Color::Yellow => Some("yellow".into()),
+        Color::Blue => Some("blue".into()),
+        Color::Magenta => Some("magenta".into()),
+        Color::Cyan => Some("cyan".into()),
+        Color::White => Some("white".into()),
+        Color::Rgb(r, g, b) => Some(format!("rgb:{:02x}/{:02x}/{:02x}", r, g, b).into()),
     }
 }
 
@@ -327,3 +465,124 @@ fn fmt_background(color: Color) -> &'static str {
 fn fmt_restore() -> &'static str {
     "22;29;24;39;49"
 }
+
+// Parses an OSC color report, such as `ESC ] 11 ; rgb:rrrr/gggg/bbbb ST`,
+// identified by `prefix` (e.g. `11` or `4;1`) within `bytes`.
+pub(crate) fn parse_color_report(bytes: &[u8], prefix: &str) -> io::Result<Color> {
+    let head = format!("\x1B]{};", prefix);
+    let start = bytes
+        .windows(head.len())
+        .position(|w| w == head.as_bytes())
+        .map(|i| i + head.len())
+        .ok_or_else(|| {
+            make_err(format!(
+                "Unable to retrieve color: missing `{}` report",
+                prefix
+            ))
+        })?;
+
+    let len = bytes[start..]
+        .iter()
+        .position(|b| *b == b'\x07' || *b == b'\x1B')
+        .ok_or_else(|| make_err("Unable to retrieve color: missing terminator"))?;
+
+    str::from_utf8(&bytes[start..start + len])
+        .ok()
+        .and_then(parse_rgb)
+        .ok_or_else(|| make_err("Unable to retrieve color: bad color specification"))
+}
+
+// From: (https://www.x.org/releases/X11R7.7/doc/libX11/libX11/libX11.html#Color_Strings)
+// >>> rgb:<red>/<green>/<blue>
+// >>> <red>, <green>, <blue> := h | hh | hhh | hhhh
+fn parse_rgb(spec: &str) -> Option<Color> {
+    let components = spec
+        .strip_prefix("rgb:")
+        .or_else(|| spec.strip_prefix("rgba:"))?;
+
+    let mut channels = components.split('/').map(|c| {
+        if !(1..=4).contains(&c.len()) || !c.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        let max = 16u32.checked_pow(u32::try_from(c.len()).ok()?)? - 1;
+        let value = u32::from_str_radix(c, 16).ok()?;
+        u8::try_from(value * 255 / max).ok()
+    });
+
+    let r = channels.next()??;
+    let g = channels.next()??;
+    let b = channels.next()??;
+    Some(Color::Rgb(r, g, b))
+}
+
+#[inline]
+#[must_use]
+fn make_err<E>(err: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::other(err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn color_reports() {
+        let sut = parse_color_report;
+
+        assert_eq!(
+            sut(b"\x1B]11;rgb:ffff/8080/0000\x1B\\", "11").unwrap(),
+            Color::Rgb(255, 128, 0)
+        );
+        assert_eq!(
+            sut(b"\x1B]10;rgb:f/80/000\x07\x1B[0n", "10").unwrap(),
+            Color::Rgb(255, 128, 0)
+        );
+        assert_eq!(
+            sut(b"\x1B]4;1;rgba:cdcd/0000/0000/ffff\x07", "4;1").unwrap(),
+            Color::Rgb(205, 0, 0)
+        );
+
+        assert!(sut(b"\x1B[0n", "11").is_err());
+        assert!(sut(b"\x1B]11;#ff8000\x07", "11").is_err());
+        assert!(sut(b"\x1B]11;rgb:ffff/8080\x07", "11").is_err());
+        assert!(sut(b"\x1B]11;rgb:fffffff/0/0\x07", "11").is_err());
+        assert!(sut(b"\x1B]11;rgb:fffff/0/0\x07", "11").is_err());
+        assert!(sut(b"\x1B]11;rgb:+f/0/0\x07", "11").is_err());
+        assert!(sut(b"\x1B]11;rgb:/0/0\x07", "11").is_err());
+    }
+
+    #[test]
+    fn themes() {
+        let colors = |foreground, background| Colors {
+            foreground,
+            background,
+        };
+
+        let (black, white) = (Color::Rgb(0, 0, 0), Color::Rgb(255, 255, 255));
+        assert_eq!(colors(white, black).theme(), Some(Theme::Dark));
+        assert_eq!(colors(black, white).theme(), Some(Theme::Light));
+        assert_eq!(colors(black, Color::Default).theme(), Some(Theme::Light));
+        assert_eq!(colors(Color::Default, Color::Default).theme(), None);
+
+        let (gray, dim_gray) = (Color::Rgb(0x77, 0x77, 0x77), Color::Rgb(0x70, 0x70, 0x70));
+        assert_eq!(colors(Color::Default, gray).theme(), Some(Theme::Light));
+        assert_eq!(colors(Color::Default, dim_gray).theme(), Some(Theme::Dark));
+        assert_eq!(colors(gray, Color::Default).theme(), Some(Theme::Dark));
+    }
+
+    #[test]
+    fn text_past_the_last_row() {
+        let mut term = Term::with_writer(Vec::new());
+        term.printer()
+            .print_text(&Text::from("a\nb\nc"), [1, u16::MAX - 1])
+            .flush()
+            .unwrap();
+
+        let output = str::from_utf8(term.stdout()).unwrap();
+        assert!(output.contains('a') && output.contains('b'));
+        assert!(!output.contains('c'));
+    }
+}
@@ -0,0 +1,212 @@
+use std::io;
+use std::mem;
+use std::panic;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::thread;
+
+use libc::{c_int, c_void, sigaction, SA_RESETHAND, SIGHUP, SIGINT, SIGTERM, SIG_DFL};
+
+// Features to be turned off on abnormal exits, kept in globals so that
+// they can be read from panic hooks and signal handlers, where `Term`
+// is out of reach.
+static ALTERNATE_BUFFER: AtomicBool = AtomicBool::new(false);
+static CURSOR_HIDDEN: AtomicBool = AtomicBool::new(false);
+static RECOLORED: AtomicU8 = AtomicU8::new(0);
+static IMAGES_PLACED: AtomicBool = AtomicBool::new(false);
+
+// Colors redefined through `Screen`, as bits of `RECOLORED`.
+pub(crate) const PALETTE: u8 = 1 << 0;
+pub(crate) const FOREGROUND: u8 = 1 << 1;
+pub(crate) const BACKGROUND: u8 = 1 << 2;
+pub(crate) const CURSOR: u8 = 1 << 3;
+
+// From: (https://invisible-island.net/xterm/ctlseqs/ctlseqs.html#h2-Operating-System-Commands)
+const COLOR_RESETS: [(u8, &[u8]); 4] = [
+    (PALETTE, b"\x1B]104\x1B\\"),
+    (FOREGROUND, b"\x1B]110\x1B\\"),
+    (BACKGROUND, b"\x1B]111\x1B\\"),
+    (CURSOR, b"\x1B]112\x1B\\"),
+];
+
+pub(crate) fn set_alternate_buffer(enabled: bool) {
+    ALTERNATE_BUFFER.store(enabled, Ordering::Relaxed);
+}
+
+pub(crate) fn set_cursor_hidden(hidden: bool) {
+    CURSOR_HIDDEN.store(hidden, Ordering::Relaxed);
+}
+
+pub(crate) fn set_images_placed(placed: bool) {
+    IMAGES_PLACED.store(placed, Ordering::Relaxed);
+}
+
+pub(crate) fn add_recolored(colors: u8) {
+    RECOLORED.fetch_or(colors, Ordering::Relaxed);
+}
+
+pub(crate) fn clear_recolored() {
+    RECOLORED.store(0, Ordering::Relaxed);
+}
+
+#[must_use]
+pub(crate) fn alternate_buffer() -> bool {
+    ALTERNATE_BUFFER.load(Ordering::Relaxed)
+}
+
+#[must_use]
+pub(crate) fn cursor_hidden() -> bool {
+    CURSOR_HIDDEN.load(Ordering::Relaxed)
+}
+
+#[must_use]
+pub(crate) fn images_placed() -> bool {
+    IMAGES_PLACED.load(Ordering::Relaxed)
+}
+
+// Prints the panic message on a usable terminal, even when `Term` doesn't
+// get dropped, e.g. with `panic = "abort"`, the previous hook is called
+// once the terminal has been restored. Only the panics of the thread
+// installing the hook, expected to own the `Term`, restore the terminal,
+// those of other threads are left to the previous hook. As hooks run
+// before unwinding, a panic of that thread caught by `catch_unwind`
+// restores it as well.
+pub fn install_panic_hook() {
+    let owner = thread::current().id();
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        if thread::current().id() == owner {
+            terminal();
+        }
+        hook(info);
+    }));
+}
+
+const FATAL_SIGNALS: [c_int; 3] = [SIGINT, SIGTERM, SIGHUP];
+
+// Restores the terminal on SIGINT, SIGTERM and SIGHUP, then lets the
+// signal terminate the process as it would have. Signals that already
+// have a handler, or are ignored (e.g. SIGHUP under `nohup`), are left
+// untouched.
+pub fn install_signal_handlers() -> io::Result<()> {
+    for signal in FATAL_SIGNALS {
+        unsafe {
+            let mut previous: sigaction = mem::zeroed();
+            if libc::sigaction(signal, ptr::null(), &mut previous) == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            if previous.sa_sigaction != SIG_DFL {
+                continue;
+            }
+
+            // The default disposition is restored as soon as the handler
+            // runs, so that the signal can be raised again.
+            let mut action: sigaction = mem::zeroed();
+            action.sa_sigaction = handle_fatal_signal as extern "C" fn(c_int) as usize;
+            action.sa_flags = SA_RESETHAND;
+            libc::sigemptyset(&mut action.sa_mask);
+            if libc::sigaction(signal, &action, ptr::null_mut()) == -1 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+extern "C" fn handle_fatal_signal(signal: c_int) {
+    terminal();
+    // Blocked until the handler returns.
+    unsafe { libc::raise(signal) };
+}
+
+// Restores the terminal to the state it was in before `Term::open`,
+// writing directly to the file descriptor, bypassing (and not waiting for)
+// the stdout lock. This function is async-signal-safe.
+pub fn terminal() {
+    if let Some(state) = crate::saved_state() {
+        crate::best_effort(crate::restore(&state, crate::UpdatePolicy::Now));
+    }
+
+    write_all(b"\x1B[0m");
+    if CURSOR_HIDDEN.swap(false, Ordering::Relaxed) {
+        write_all(b"\x1B[?25h");
+    }
+    // From: (https://sw.kovidgoyal.net/kitty/graphics-protocol/#deleting-images)
+    // >>> a or A - Delete all placements visible on screen
+    // placements belong to the buffer they were made on, so they have to
+    // go before leaving the alternate buffer.
+    if IMAGES_PLACED.swap(false, Ordering::Relaxed) {
+        write_all(b"\x1B_Ga=d,d=a,q=2\x1B\\");
+    }
+    if ALTERNATE_BUFFER.swap(false, Ordering::Relaxed) {
+        write_all(b"\x1B[?1049l");
+    }
+
+    let recolored = RECOLORED.swap(0, Ordering::Relaxed);
+    for (color, reset) in COLOR_RESETS {
+        if recolored & color != 0 {
+            write_all(reset);
+        }
+    }
+}
+
+fn write_all(mut bytes: &[u8]) {
+    while !bytes.is_empty() {
+        match unsafe {
+            libc::write(
+                crate::output_fileno(),
+                bytes.as_ptr() as *const c_void,
+                bytes.len(),
+            )
+        } {
+            n @ 1.. => bytes = &bytes[n as usize..],
+            -1 if errno() == libc::EINTR => continue,
+            _ => return,
+        }
+    }
+}
+
+#[must_use]
+fn errno() -> i32 {
+    io::Error::last_os_error().raw_os_error().unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process::Command;
+
+    const CHILD_ENV: &str = "TERMULAR_RESTORE_CHILD";
+
+    #[test]
+    fn panic_hook_child() {
+        if env::var_os(CHILD_ENV).is_none() {
+            return;
+        }
+
+        install_panic_hook();
+        set_cursor_hidden(true);
+
+        assert!(thread::spawn(|| panic!("worker")).join().is_err());
+        println!("hidden after the worker panic: {}", cursor_hidden());
+
+        assert!(panic::catch_unwind(|| panic!("owner")).is_err());
+        println!("hidden after the owner panic: {}", cursor_hidden());
+    }
+
+    #[test]
+    fn panic_hook() {
+        let output = Command::new(env::current_exe().unwrap())
+            .args(["restore::tests::panic_hook_child", "--exact", "--nocapture"])
+            .env(CHILD_ENV, "1")
+            .output()
+            .unwrap();
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(output.status.success(), "{}", stdout);
+        assert!(stdout.contains("hidden after the worker panic: true"));
+        assert!(stdout.contains("\x1B[?25hhidden after the owner panic: false"));
+    }
+}
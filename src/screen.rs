@@ -1,9 +1,12 @@
-use crate::cursor::Cursor;
+use crate::cursor::{self, Cursor};
 use crate::flow::Flow;
-use crate::printer::Printer;
+use crate::graphics::kitty::{self, Placement};
+use crate::graphics::{iterm, sixel, Image, Protocol};
+use crate::printer::{self, Color, Printer};
+use crate::restore;
 use crate::Term;
 
-use std::io::{self, Write};
+use std::io::{self, StderrLock, StdoutLock, Write};
 
 #[derive(Default, Debug, PartialEq, Eq, Copy, Clone)]
 pub enum Buffer {
@@ -12,49 +15,259 @@ pub enum Buffer {
     Alternate,
 }
 
+// Area the application renders to, either the whole screen or `height`
+// rows on the primary buffer starting at the cursor row, which are left
+// in the scrollback once `Term` gets dropped.
+#[derive(Default, Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Viewport {
+    #[default]
+    Fullscreen,
+    Inline {
+        height: u16,
+    },
+}
+
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
 pub enum Scroll {
     Up(u16),
     Down(u16),
 }
 
-pub struct Screen<'a: 'b, 'b>(pub(crate) io::Result<&'b mut Term<'a>>);
+pub struct Screen<'a: 'b, 'b, W: Write = StdoutLock<'a>, E: Write = StderrLock<'a>>(
+    pub(crate) io::Result<&'b mut Term<'a, W, E>>,
+);
 
-impl<'a, 'b> Screen<'a, 'b> {
+impl<'a, 'b, W: Write, E: Write> Screen<'a, 'b, W, E> {
     #[must_use]
-    pub fn cursor(self) -> Cursor<'a, 'b> {
+    pub fn cursor(self) -> Cursor<'a, 'b, W, E> {
         Cursor(self.0)
     }
 
     #[must_use]
-    pub fn printer(self) -> Printer<'a, 'b> {
+    pub fn printer(self) -> Printer<'a, 'b, W, E> {
         Printer(self.0)
     }
 
     #[must_use]
-    pub fn flow(self) -> Flow<'a, 'b> {
+    pub fn flow(self) -> Flow<'a, 'b, W, E> {
         Flow(self.0)
     }
 
     #[must_use]
     pub fn set_buffer(self, buffer: Buffer) -> Self {
-        self.chain(|t| match buffer {
-            Buffer::Primary => write!(t.stdout_mut(), "\x1B[?1049l"),
-            Buffer::Alternate => write!(t.stdout_mut(), "\x1B[?1049h"),
+        self.chain(|t| {
+            if t.tty {
+                restore::set_alternate_buffer(buffer == Buffer::Alternate);
+            }
+            match buffer {
+                Buffer::Primary => t.write_capability("rmcup", &[], format_args!("\x1B[?1049l")),
+                Buffer::Alternate => t.write_capability("smcup", &[], format_args!("\x1B[?1049h")),
+            }
+        })
+    }
+
+    // Inline viewports start on the cursor row, or the next one if the row
+    // isn't empty, scrolling the terminal to make room when needed.
+    #[must_use]
+    pub fn set_viewport(self, viewport: Viewport) -> Self {
+        self.chain(|t| {
+            let (viewport, origin) = match viewport {
+                Viewport::Fullscreen => (viewport, [0, 0].into()),
+                Viewport::Inline { height } => {
+                    let height = height.clamp(1, t.size()?[1].max(1));
+                    if t.cursor().position()?[0] > 0 {
+                        t.stdout_mut().write_all(b"\r\n")?;
+                    }
+
+                    // Line feeds on the last row scroll the terminal up.
+                    let feeds = "\n".repeat(usize::from(height - 1));
+                    write!(t.stdout_mut(), "\r{}", feeds)?;
+                    let bottom = t.cursor().position()?[1];
+                    (
+                        Viewport::Inline { height },
+                        [0, bottom.saturating_sub(height - 1)].into(),
+                    )
+                }
+            };
+
+            t.viewport = viewport;
+            t.viewport_origin = origin;
+            Ok(())
+        })
+    }
+
+    // Clears the whole screen or just the inline viewport, moving the
+    // cursor to the origin of the viewport.
+    #[must_use]
+    pub fn clear_viewport(self) -> Self {
+        self.chain(|t| match t.viewport {
+            Viewport::Fullscreen => write_clear(t),
+            Viewport::Inline { .. } => {
+                let origin = t.viewport_origin;
+                cursor::write_position(t, origin)?;
+                t.write_capability("ed", &[], format_args!("\x1B[0J"))
+            }
         })
     }
 
     #[must_use]
     pub fn scroll(self, scroll: Scroll) -> Self {
         self.chain(|t| match scroll {
-            Scroll::Up(rows) => write!(t.stdout_mut(), "\x1B[{}S", rows),
-            Scroll::Down(rows) => write!(t.stdout_mut(), "\x1B[{}T", rows),
+            Scroll::Up(rows) => {
+                t.write_capability("indn", &[rows.into()], format_args!("\x1B[{}S", rows))
+            }
+            Scroll::Down(rows) => {
+                t.write_capability("rin", &[rows.into()], format_args!("\x1B[{}T", rows))
+            }
         })
     }
 
     #[must_use]
     pub fn clear(self) -> Self {
-        self.chain(|t| write!(t.stdout_mut(), "\x1B[2J\x1B[1;1H"))
+        self.chain(write_clear)
+    }
+
+    #[must_use]
+    pub fn set_palette(self, index: u8, color: Color) -> Self {
+        self.chain(|t| {
+            t.recolored.palette.retain(|(i, _)| *i != index);
+            t.recolored.palette.push((index, color));
+            track_recolored(t, restore::PALETTE);
+            match printer::fmt_color_spec(color) {
+                Some(spec) => write!(t.stdout_mut(), "\x1B]4;{};{}\x1B\\", index, spec),
+                None => write!(t.stdout_mut(), "\x1B]104;{}\x1B\\", index),
+            }
+        })
+    }
+
+    #[must_use]
+    pub fn set_default_foreground(self, color: Color) -> Self {
+        self.chain(|t| {
+            t.recolored.foreground = Some(color);
+            track_recolored(t, restore::FOREGROUND);
+            write_dynamic_color(t, 10, color)
+        })
+    }
+
+    #[must_use]
+    pub fn set_default_background(self, color: Color) -> Self {
+        self.chain(|t| {
+            t.recolored.background = Some(color);
+            track_recolored(t, restore::BACKGROUND);
+            write_dynamic_color(t, 11, color)
+        })
+    }
+
+    #[must_use]
+    pub fn set_cursor_color(self, color: Color) -> Self {
+        self.chain(|t| {
+            t.recolored.cursor = Some(color);
+            track_recolored(t, restore::CURSOR);
+            write_dynamic_color(t, 12, color)
+        })
+    }
+
+    // Resets only the colors that have been changed through this `Term`,
+    // so that we don't override the user's own customizations.
+    #[must_use]
+    pub fn reset_colors(self) -> Self {
+        self.chain(|t| {
+            let recolored = std::mem::take(&mut t.recolored);
+            if t.tty {
+                restore::clear_recolored();
+            }
+
+            if !recolored.palette.is_empty() {
+                write!(t.stdout_mut(), "\x1B]104\x1B\\")?;
+            }
+
+            [recolored.foreground, recolored.background, recolored.cursor]
+                .into_iter()
+                .zip([110, 111, 112])
+                .filter(|(changed, _)| changed.is_some())
+                .try_for_each(|(_, code)| write!(t.stdout_mut(), "\x1B]{}\x1B\\", code))
+        })
+    }
+
+    // The image is drawn at the cursor position.
+    #[must_use]
+    pub fn draw_sixel(self, image: &Image) -> Self {
+        self.chain(|t| sixel::encode(t.stdout_mut(), image, sixel::MAX_COLORS))
+    }
+
+    // The image is drawn at the cursor position.
+    #[must_use]
+    pub fn draw_image(self, image: &Image, protocol: Protocol) -> Self {
+        self.chain(|t| match protocol {
+            Protocol::Kitty => {
+                track_images(t);
+                kitty::display(t.stdout_mut(), image)
+            }
+            Protocol::Iterm => {
+                iterm::encode(t.stdout_mut(), &iterm::png(image), &Default::default())
+            }
+            Protocol::Sixel => sixel::encode(t.stdout_mut(), image, sixel::MAX_COLORS),
+        })
+    }
+
+    // Draws an image file (PNG, JPEG, GIF, ...) at the cursor position
+    // through the iTerm2 inline images protocol.
+    #[must_use]
+    pub fn draw_inline_image(self, file: &[u8], options: &iterm::Options) -> Self {
+        self.chain(|t| iterm::encode(t.stdout_mut(), file, options))
+    }
+
+    #[must_use]
+    pub fn transmit_image(self, image_id: u32, image: &Image) -> Self {
+        self.chain(|t| {
+            if !t.images.contains(&image_id) {
+                t.images.push(image_id);
+            }
+            kitty::transmit(t.stdout_mut(), image_id, image)
+        })
+    }
+
+    // The image is placed at the cursor position.
+    #[must_use]
+    pub fn place_image(self, image_id: u32, placement: &Placement) -> Self {
+        self.chain(|t| {
+            track_images(t);
+            kitty::place(t.stdout_mut(), image_id, placement)
+        })
+    }
+
+    #[must_use]
+    pub fn delete_placement(self, image_id: u32, placement_id: u32) -> Self {
+        self.chain(|t| kitty::delete_placement(t.stdout_mut(), image_id, placement_id))
+    }
+
+    #[must_use]
+    pub fn delete_image(self, image_id: u32) -> Self {
+        self.chain(|t| {
+            t.images.retain(|id| *id != image_id);
+            kitty::delete_image(t.stdout_mut(), image_id)
+        })
+    }
+
+    // Deletes the placements visible on screen, keeping the images.
+    #[must_use]
+    pub fn delete_placements(self) -> Self {
+        self.chain(|t| {
+            if t.tty {
+                restore::set_images_placed(false);
+            }
+            kitty::delete_placements(t.stdout_mut())
+        })
+    }
+
+    // Deletes only the images that have been transmitted through this `Term`.
+    #[must_use]
+    pub fn delete_images(self) -> Self {
+        self.chain(|t| {
+            std::mem::take(&mut t.images)
+                .into_iter()
+                .try_for_each(|id| kitty::delete_image(t.stdout_mut(), id))
+        })
     }
 
     pub fn flush(self) -> io::Result<()> {
@@ -65,8 +278,37 @@ impl<'a, 'b> Screen<'a, 'b> {
     #[must_use]
     fn chain<F>(self, f: F) -> Self
     where
-        F: FnOnce(&mut Term) -> io::Result<()>,
+        F: FnOnce(&mut Term<'a, W, E>) -> io::Result<()>,
     {
         Self(self.0.and_then(|t| f(t).map(|_| t)))
     }
 }
+
+fn write_clear<W: Write, E: Write>(t: &mut Term<'_, W, E>) -> io::Result<()> {
+    t.write_capability("clear", &[], format_args!("\x1B[2J\x1B[1;1H"))
+}
+
+// Colors are reset on abnormal exits too, see `restore::terminal`.
+fn track_recolored<W: Write, E: Write>(t: &Term<'_, W, E>, colors: u8) {
+    if t.tty {
+        restore::add_recolored(colors);
+    }
+}
+
+// Placements are deleted on abnormal exits, see `restore::terminal`.
+fn track_images<W: Write, E: Write>(t: &Term<'_, W, E>) {
+    if t.tty {
+        restore::set_images_placed(true);
+    }
+}
+
+fn write_dynamic_color<W: Write, E: Write>(
+    t: &mut Term<'_, W, E>,
+    code: u8,
+    color: Color,
+) -> io::Result<()> {
+    match printer::fmt_color_spec(color) {
+        Some(spec) => write!(t.stdout_mut(), "\x1B]{};{}\x1B\\", code, spec),
+        None => write!(t.stdout_mut(), "\x1B]{}\x1B\\", code + 100),
+    }
+}
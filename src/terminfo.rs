@@ -0,0 +1,409 @@
+mod names;
+mod parm;
+
+pub use crate::terminfo::parm::{expand, Param};
+
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// From: (https://invisible-island.net/ncurses/man/term.5.html)
+// >>> The first word is the magic number, the legacy format uses 16-bit
+// >>> signed integers for numbers while the extended number format uses
+// >>> 32-bit signed integers.
+const MAGIC_LEGACY: i16 = 0o432;
+const MAGIC_EXTENDED: i16 = 0o1036;
+
+const SYSTEM_DIRS: [&str; 5] = [
+    "/etc/terminfo",
+    "/lib/terminfo",
+    "/usr/share/terminfo",
+    "/usr/lib/terminfo",
+    "/usr/local/share/terminfo",
+];
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TermInfo {
+    names: Vec<String>,
+    booleans: HashSet<String>,
+    numbers: HashMap<String, i32>,
+    strings: HashMap<String, Vec<u8>>,
+}
+
+impl TermInfo {
+    pub fn from_env() -> io::Result<Self> {
+        let name = env::var("TERM").map_err(|_| invalid_input("TERM is not set"))?;
+        Self::from_name(&name)
+    }
+
+    pub fn from_name(name: &str) -> io::Result<Self> {
+        let first = name
+            .chars()
+            .next()
+            .filter(|_| !name.contains('/'))
+            .ok_or_else(|| invalid_input(format!("Invalid terminal name `{}`", name)))?;
+
+        // Entries are either stored under the first letter of their name
+        // or, on case-insensitive file systems, under its hex encoding.
+        search_dirs()
+            .into_iter()
+            .flat_map(|dir| {
+                [
+                    dir.join(first.to_string()).join(name),
+                    dir.join(format!("{:x}", first as u32)).join(name),
+                ]
+            })
+            .find(|path| path.is_file())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("Unable to find terminfo entry for `{}`", name),
+                )
+            })
+            .and_then(Self::from_path)
+    }
+
+    pub fn from_path(path: impl AsRef<Path>) -> io::Result<Self> {
+        fs::read(path).and_then(|bytes| Self::parse(&bytes))
+    }
+
+    pub fn parse(bytes: &[u8]) -> io::Result<Self> {
+        let mut reader = Reader { bytes, offset: 0 };
+
+        let number_size = match reader.i16()? {
+            MAGIC_LEGACY => 2,
+            MAGIC_EXTENDED => 4,
+            magic => return Err(invalid_data(format!("Bad magic number `{:#o}`", magic))),
+        };
+
+        let names_size = reader.count()?;
+        let booleans_count = reader.count()?;
+        let numbers_count = reader.count()?;
+        let strings_count = reader.count()?;
+        let table_size = reader.count()?;
+
+        let names = reader
+            .take(names_size)
+            .map(|b| until_nul(b).split(|b| *b == b'|'))?
+            .map(|name| String::from_utf8_lossy(name).into_owned())
+            .collect();
+
+        let mut info = Self {
+            names,
+            ..Default::default()
+        };
+
+        let booleans = reader.take(booleans_count)?;
+        reader.align();
+        let numbers = reader.numbers(numbers_count, number_size)?;
+        let offsets = reader.offsets(strings_count)?;
+        let table = reader.take(table_size)?;
+        reader.align();
+
+        info.booleans.extend(
+            names::BOOLEANS
+                .iter()
+                .zip(booleans)
+                .filter(|(_, value)| **value == 1)
+                .map(|(name, _)| name.to_string()),
+        );
+
+        info.numbers.extend(
+            names::NUMBERS
+                .iter()
+                .zip(numbers)
+                .filter_map(|(name, value)| value.map(|v| (name.to_string(), v))),
+        );
+
+        for (name, offset) in names::STRINGS.iter().zip(offsets) {
+            if let Some(value) = offset.map(|o| table_string(table, o)).transpose()? {
+                info.strings.insert(name.to_string(), value.to_vec());
+            }
+        }
+
+        if !reader.is_empty() {
+            info.parse_extended(&mut reader, number_size)?;
+        }
+
+        Ok(info)
+    }
+
+    fn parse_extended(&mut self, reader: &mut Reader, number_size: usize) -> io::Result<()> {
+        let booleans_count = reader.count()?;
+        let numbers_count = reader.count()?;
+        let strings_count = reader.count()?;
+        let _items_count = reader.count()?;
+        let table_size = reader.count()?;
+
+        let booleans = reader.take(booleans_count)?;
+        reader.align();
+        let numbers = reader.numbers(numbers_count, number_size)?;
+        let offsets = reader.offsets(strings_count)?;
+        let names_offsets = reader.offsets(booleans_count + numbers_count + strings_count)?;
+        let table = reader.take(table_size)?;
+
+        // String values come first in the table, capability names follow,
+        // names offsets are relative to the end of the last string value.
+        let names_base = offsets
+            .iter()
+            .flatten()
+            .map(|&o| table_string(table, o).map(|s| o + s.len() + 1))
+            .try_fold(0, |acc, end| end.map(|end| acc.max(end)))?;
+
+        let names = names_offsets
+            .into_iter()
+            .map(|o| {
+                let o = o.ok_or_else(|| invalid_data("Missing extended capability name"))?;
+                table_string(table, names_base + o)
+                    .map(|name| String::from_utf8_lossy(name).into_owned())
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let (boolean_names, rest) = names.split_at(booleans_count);
+        let (number_names, string_names) = rest.split_at(numbers_count);
+
+        for (name, value) in boolean_names.iter().zip(booleans) {
+            if *value == 1 {
+                self.booleans.insert(name.clone());
+            } else {
+                self.booleans.remove(name);
+            }
+        }
+
+        for (name, value) in number_names.iter().zip(numbers) {
+            match value {
+                Some(value) => self.numbers.insert(name.clone(), value),
+                None => self.numbers.remove(name),
+            };
+        }
+
+        for (name, offset) in string_names.iter().zip(offsets) {
+            match offset.map(|o| table_string(table, o)).transpose()? {
+                Some(value) => self.strings.insert(name.clone(), value.to_vec()),
+                None => self.strings.remove(name),
+            };
+        }
+
+        Ok(())
+    }
+
+    #[must_use]
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    #[must_use]
+    pub fn boolean(&self, name: &str) -> bool {
+        self.booleans.contains(name)
+    }
+
+    #[must_use]
+    pub fn number(&self, name: &str) -> Option<i32> {
+        self.numbers.get(name).copied()
+    }
+
+    #[must_use]
+    pub fn string(&self, name: &str) -> Option<&[u8]> {
+        self.strings.get(name).map(Vec::as_slice)
+    }
+
+    pub fn expand(&self, name: &str, params: &[Param]) -> Option<io::Result<Vec<u8>>> {
+        self.string(name).map(|cap| parm::expand(cap, params))
+    }
+}
+
+// From: (https://invisible-island.net/ncurses/man/terminfo.5.html#h3-Fetching-Compiled-Descriptions)
+#[must_use]
+fn search_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+
+    if let Some(dir) = env::var_os("TERMINFO") {
+        dirs.push(PathBuf::from(dir));
+    }
+
+    if let Some(home) = env::var_os("HOME") {
+        dirs.push(PathBuf::from(home).join(".terminfo"));
+    }
+
+    // An empty entry in TERMINFO_DIRS stands for the system directories.
+    match env::var_os("TERMINFO_DIRS") {
+        Some(value) => env::split_paths(&value).for_each(|dir| {
+            if dir.as_os_str().is_empty() {
+                dirs.extend(SYSTEM_DIRS.iter().map(PathBuf::from));
+            } else {
+                dirs.push(dir);
+            }
+        }),
+        None => dirs.extend(SYSTEM_DIRS.iter().map(PathBuf::from)),
+    }
+
+    dirs.dedup();
+    dirs
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn is_empty(&self) -> bool {
+        self.offset >= self.bytes.len()
+    }
+
+    fn align(&mut self) {
+        if !self.offset.is_multiple_of(2) && !self.is_empty() {
+            self.offset += 1;
+        }
+    }
+
+    fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        let end = self.offset.saturating_add(n);
+        let out = self
+            .bytes
+            .get(self.offset..end)
+            .ok_or_else(|| invalid_data("Unexpected end of terminfo entry"))?;
+        self.offset = end;
+        Ok(out)
+    }
+
+    fn i16(&mut self) -> io::Result<i16> {
+        self.take(2).map(|b| i16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn i32(&mut self) -> io::Result<i32> {
+        self.take(4)
+            .map(|b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn count(&mut self) -> io::Result<usize> {
+        self.i16().and_then(|n| {
+            usize::try_from(n).map_err(|_| invalid_data(format!("Bad section size `{}`", n)))
+        })
+    }
+
+    // Negative numbers mean absent or cancelled capabilities.
+    fn numbers(&mut self, n: usize, size: usize) -> io::Result<Vec<Option<i32>>> {
+        (0..n)
+            .map(|_| match size {
+                2 => self.i16().map(i32::from),
+                _ => self.i32(),
+            })
+            .map(|value| value.map(|v| Some(v).filter(|v| *v >= 0)))
+            .collect()
+    }
+
+    // Negative offsets mean absent or cancelled capabilities.
+    fn offsets(&mut self, n: usize) -> io::Result<Vec<Option<usize>>> {
+        (0..n)
+            .map(|_| self.i16().map(|o| usize::try_from(o).ok()))
+            .collect()
+    }
+}
+
+fn table_string(table: &[u8], offset: usize) -> io::Result<&[u8]> {
+    table
+        .get(offset..)
+        .map(until_nul)
+        .ok_or_else(|| invalid_data("String offset out of bounds"))
+}
+
+#[must_use]
+fn until_nul(bytes: &[u8]) -> &[u8] {
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    &bytes[..end]
+}
+
+#[must_use]
+fn invalid_data<E>(err: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+#[must_use]
+fn invalid_input<E>(err: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidInput, err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Builds a compiled entry: `bw`, `cols#80` and `cup`, plus the
+    // extended capabilities `AX`, `RGB#24` and `Ss`.
+    fn entry(magic: i16, extended: bool) -> Vec<u8> {
+        let number = |n: i32| match magic {
+            MAGIC_LEGACY => (n as i16).to_le_bytes().to_vec(),
+            _ => n.to_le_bytes().to_vec(),
+        };
+
+        let names = b"dumb|a dumb terminal\0";
+        let table = b"\x1B[%i%p1%d;%p2%dH\0";
+
+        let mut out = Vec::new();
+        for n in [magic, names.len() as i16, 1, 1, 11, table.len() as i16] {
+            out.extend(n.to_le_bytes());
+        }
+        out.extend(names);
+        out.push(1);
+        out.extend(number(80));
+        for offset in [-1i16; 10].into_iter().chain([0]) {
+            out.extend(offset.to_le_bytes());
+        }
+        out.extend(table);
+
+        if extended {
+            let table = b"\x1B[%p1%d q\0AX\0RGB\0Ss\0";
+            if !out.len().is_multiple_of(2) {
+                out.push(0);
+            }
+            for n in [1i16, 1, 1, 4, table.len() as i16] {
+                out.extend(n.to_le_bytes());
+            }
+            out.push(1);
+            out.push(0);
+            out.extend(number(24));
+            for offset in [0i16, 0, 3, 7] {
+                out.extend(offset.to_le_bytes());
+            }
+            out.extend(table);
+        }
+
+        out
+    }
+
+    #[test]
+    fn parse() {
+        for magic in [MAGIC_LEGACY, MAGIC_EXTENDED] {
+            let sut = TermInfo::parse(&entry(magic, true)).unwrap();
+
+            assert_eq!(sut.names(), ["dumb", "a dumb terminal"]);
+            assert!(sut.boolean("bw"));
+            assert!(!sut.boolean("am"));
+            assert_eq!(sut.number("cols"), Some(80));
+            assert_eq!(sut.number("lines"), None);
+            assert_eq!(sut.string("cup"), Some(&b"\x1B[%i%p1%d;%p2%dH"[..]));
+            assert_eq!(sut.string("clear"), None);
+
+            assert!(sut.boolean("AX"));
+            assert_eq!(sut.number("RGB"), Some(24));
+            assert_eq!(sut.string("Ss"), Some(&b"\x1B[%p1%d q"[..]));
+
+            let cup = sut.expand("cup", &[4.into(), 2.into()]).unwrap().unwrap();
+            assert_eq!(cup, b"\x1B[5;3H");
+        }
+
+        let sut = TermInfo::parse(&entry(MAGIC_LEGACY, false)).unwrap();
+        assert_eq!(sut.number("RGB"), None);
+
+        assert!(TermInfo::parse(b"\x00\x00").is_err());
+        assert!(TermInfo::parse(&entry(MAGIC_LEGACY, true)[..40]).is_err());
+    }
+}
@@ -0,0 +1,418 @@
+use std::io;
+
+// From: (https://invisible-island.net/ncurses/man/terminfo.5.html#h3-Parameterized-Strings)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Param {
+    Number(i32),
+    String(Vec<u8>),
+}
+
+impl Default for Param {
+    fn default() -> Self {
+        Self::Number(0)
+    }
+}
+
+impl From<i32> for Param {
+    fn from(value: i32) -> Self {
+        Self::Number(value)
+    }
+}
+
+impl From<u16> for Param {
+    fn from(value: u16) -> Self {
+        Self::Number(i32::from(value))
+    }
+}
+
+impl From<&str> for Param {
+    fn from(value: &str) -> Self {
+        Self::String(value.as_bytes().to_vec())
+    }
+}
+
+impl Param {
+    fn number(self) -> i32 {
+        match self {
+            Self::Number(n) => n,
+            Self::String(_) => 0,
+        }
+    }
+
+    fn bytes(self) -> Vec<u8> {
+        match self {
+            Self::Number(n) => n.to_string().into_bytes(),
+            Self::String(s) => s,
+        }
+    }
+}
+
+// From: (https://invisible-island.net/ncurses/man/terminfo.5.html#h3-Delays-and-Padding)
+// >>> A delay in milliseconds may appear anywhere in a string capability,
+// >>> enclosed in $<..> brackets, as in el=\EK$<5>
+// Length of the `<digits[.digit][*][/]>` group following a `$`, if any,
+// terminals don't need padding nowadays, so it's dropped.
+#[must_use]
+fn padding_len(bytes: &[u8]) -> Option<usize> {
+    let [b'<', rest @ ..] = bytes else {
+        return None;
+    };
+
+    let digits = rest.iter().take_while(|b| b.is_ascii_digit()).count();
+    let mut len = digits;
+    if let [b'.', b'0'..=b'9', ..] = &rest[len..] {
+        len += 2;
+    }
+    if rest.get(len) == Some(&b'*') {
+        len += 1;
+    }
+    if rest.get(len) == Some(&b'/') {
+        len += 1;
+    }
+
+    (digits > 0 && rest.get(len) == Some(&b'>')).then_some(len + 2)
+}
+
+pub fn expand(cap: &[u8], params: &[Param]) -> io::Result<Vec<u8>> {
+    let mut argv: [Param; 9] = Default::default();
+    argv.iter_mut()
+        .zip(params)
+        .for_each(|(dst, src)| *dst = src.clone());
+
+    let mut out = Vec::with_capacity(cap.len());
+    let mut stack = Stack::default();
+    let mut dynamic: [Param; 26] = Default::default();
+    let mut fixed: [Param; 26] = Default::default();
+    let mut i = 0;
+
+    while i < cap.len() {
+        let byte = cap[i];
+        i += 1;
+
+        if byte == b'$' {
+            if let Some(len) = padding_len(&cap[i..]) {
+                i += len;
+                continue;
+            }
+        }
+
+        if byte != b'%' {
+            out.push(byte);
+            continue;
+        }
+
+        let op = *cap.get(i).ok_or_else(|| malformed("dangling `%`"))?;
+        i += 1;
+
+        match op {
+            b'%' => out.push(b'%'),
+            b'c' => out.push(stack.pop()?.number() as u8),
+            b'p' => {
+                let n = digit(cap.get(i), b'1'..=b'9')?;
+                i += 1;
+                stack.push(argv[usize::from(n - b'1')].clone());
+            }
+            b'P' | b'g' => {
+                let name = *cap.get(i).ok_or_else(|| malformed("missing variable"))?;
+                i += 1;
+                let var = match name {
+                    b'a'..=b'z' => &mut dynamic[usize::from(name - b'a')],
+                    b'A'..=b'Z' => &mut fixed[usize::from(name - b'A')],
+                    _ => return Err(malformed("bad variable name")),
+                };
+                if op == b'P' {
+                    *var = stack.pop()?;
+                } else {
+                    stack.push(var.clone());
+                }
+            }
+            b'\'' => {
+                let c = *cap.get(i).ok_or_else(|| malformed("missing character"))?;
+                if cap.get(i + 1) != Some(&b'\'') {
+                    return Err(malformed("unterminated character constant"));
+                }
+                i += 2;
+                stack.push(Param::Number(i32::from(c)));
+            }
+            b'{' => {
+                let len = cap[i..]
+                    .iter()
+                    .position(|b| *b == b'}')
+                    .ok_or_else(|| malformed("unterminated integer constant"))?;
+                let n = std::str::from_utf8(&cap[i..i + len])
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .ok_or_else(|| malformed("bad integer constant"))?;
+                i += len + 1;
+                stack.push(Param::Number(n));
+            }
+            b'l' => {
+                let len = stack.pop()?.bytes().len();
+                stack.push(Param::Number(len as i32));
+            }
+            b'+' | b'-' | b'*' | b'/' | b'm' | b'&' | b'|' | b'^' | b'=' | b'>' | b'<' | b'A'
+            | b'O' => {
+                let rhs = stack.pop()?.number();
+                let lhs = stack.pop()?.number();
+                stack.push(Param::Number(binary(op, lhs, rhs)));
+            }
+            b'!' => {
+                let n = stack.pop()?.number();
+                stack.push(Param::Number(i32::from(n == 0)));
+            }
+            b'~' => {
+                let n = stack.pop()?.number();
+                stack.push(Param::Number(!n));
+            }
+            b'i' => argv.iter_mut().take(2).for_each(|p| {
+                if let Param::Number(n) = p {
+                    *n += 1;
+                }
+            }),
+            b'?' | b';' => {}
+            b't' => {
+                if stack.pop()?.number() == 0 {
+                    i = skip(cap, i, true);
+                }
+            }
+            b'e' => i = skip(cap, i, false),
+            _ => {
+                let (format, len) = Format::parse(&cap[i - 1..])?;
+                i += len - 1;
+                format.write(stack.pop()?, &mut out);
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+#[derive(Default)]
+struct Stack(Vec<Param>);
+
+impl Stack {
+    fn push(&mut self, param: Param) {
+        self.0.push(param);
+    }
+
+    fn pop(&mut self) -> io::Result<Param> {
+        self.0.pop().ok_or_else(|| malformed("stack underflow"))
+    }
+}
+
+fn binary(op: u8, lhs: i32, rhs: i32) -> i32 {
+    match op {
+        b'+' => lhs.wrapping_add(rhs),
+        b'-' => lhs.wrapping_sub(rhs),
+        b'*' => lhs.wrapping_mul(rhs),
+        b'/' => lhs.checked_div(rhs).unwrap_or(0),
+        b'm' => lhs.checked_rem(rhs).unwrap_or(0),
+        b'&' => lhs & rhs,
+        b'|' => lhs | rhs,
+        b'^' => lhs ^ rhs,
+        b'=' => i32::from(lhs == rhs),
+        b'>' => i32::from(lhs > rhs),
+        b'<' => i32::from(lhs < rhs),
+        b'A' => i32::from(lhs != 0 && rhs != 0),
+        b'O' => i32::from(lhs != 0 || rhs != 0),
+        _ => unreachable!(),
+    }
+}
+
+// Moves past the `%e` (only when `to_else`) or `%;` matching the current
+// conditional, nested conditionals are skipped as a whole.
+fn skip(cap: &[u8], mut i: usize, to_else: bool) -> usize {
+    let mut depth = 0;
+
+    while i + 1 < cap.len() {
+        if cap[i] != b'%' {
+            i += 1;
+            continue;
+        }
+
+        match cap[i + 1] {
+            b'?' => depth += 1,
+            b';' if depth == 0 => return i + 2,
+            b';' => depth -= 1,
+            b'e' if depth == 0 && to_else => return i + 2,
+            _ => {}
+        }
+
+        i += 2;
+    }
+
+    cap.len()
+}
+
+// Widths and precisions are clamped, so that a corrupt entry can't pad
+// the output up to gigabytes.
+const MAX_WIDTH: usize = 512;
+
+#[derive(Debug, Default, Copy, Clone)]
+struct Format {
+    left: bool,
+    sign: bool,
+    space: bool,
+    alternate: bool,
+    zero: bool,
+    width: usize,
+    precision: Option<usize>,
+    conversion: u8,
+}
+
+impl Format {
+    // Parses `[:][flags][width[.precision]][doxXs]` returning the format
+    // and the number of bytes consumed.
+    fn parse(bytes: &[u8]) -> io::Result<(Self, usize)> {
+        let mut format = Self::default();
+        let mut i = usize::from(bytes.first() == Some(&b':'));
+
+        while let Some(flag) = bytes.get(i) {
+            match flag {
+                b'-' => format.left = true,
+                b'+' => format.sign = true,
+                b' ' => format.space = true,
+                b'#' => format.alternate = true,
+                b'0' => format.zero = true,
+                _ => break,
+            }
+            i += 1;
+        }
+
+        let (width, len) = number(&bytes[i..]);
+        format.width = width.min(MAX_WIDTH);
+        i += len;
+
+        if bytes.get(i) == Some(&b'.') {
+            let (precision, len) = number(&bytes[i + 1..]);
+            format.precision = Some(precision.min(MAX_WIDTH));
+            i += len + 1;
+        }
+
+        match bytes.get(i) {
+            Some(c @ (b'd' | b'o' | b'x' | b'X' | b's')) => format.conversion = *c,
+            _ => return Err(malformed("unknown operator")),
+        }
+
+        Ok((format, i + 1))
+    }
+
+    fn write(&self, param: Param, out: &mut Vec<u8>) {
+        let mut body = match self.conversion {
+            b's' => {
+                let mut s = param.bytes();
+                if let Some(precision) = self.precision {
+                    s.truncate(precision);
+                }
+                s
+            }
+            conversion => {
+                let n = param.number();
+                let digits = match conversion {
+                    b'o' => format!("{:o}", n.unsigned_abs()),
+                    b'x' => format!("{:x}", n.unsigned_abs()),
+                    b'X' => format!("{:X}", n.unsigned_abs()),
+                    _ => n.unsigned_abs().to_string(),
+                };
+                let digits = format!("{:0>1$}", digits, self.precision.unwrap_or(0));
+                let prefix = match conversion {
+                    _ if n < 0 => "-",
+                    b'd' if self.sign => "+",
+                    b'd' if self.space => " ",
+                    b'o' if self.alternate && !digits.starts_with('0') => "0",
+                    b'x' if self.alternate && n != 0 => "0x",
+                    b'X' if self.alternate && n != 0 => "0X",
+                    _ => "",
+                };
+                let padding = self.width.saturating_sub(prefix.len() + digits.len());
+                if self.zero && !self.left && self.precision.is_none() {
+                    format!("{}{}{}", prefix, "0".repeat(padding), digits).into_bytes()
+                } else {
+                    format!("{}{}", prefix, digits).into_bytes()
+                }
+            }
+        };
+
+        let padding = self.width.saturating_sub(body.len());
+        if self.left {
+            body.resize(body.len() + padding, b' ');
+        } else {
+            out.resize(out.len() + padding, b' ');
+        }
+
+        out.append(&mut body);
+    }
+}
+
+fn number(bytes: &[u8]) -> (usize, usize) {
+    let len = bytes.iter().take_while(|b| b.is_ascii_digit()).count();
+    let n = bytes[..len].iter().fold(0usize, |acc, b| {
+        acc.saturating_mul(10) + usize::from(b - b'0')
+    });
+    (n, len)
+}
+
+fn digit(byte: Option<&u8>, range: std::ops::RangeInclusive<u8>) -> io::Result<u8> {
+    byte.copied()
+        .filter(|b| range.contains(b))
+        .ok_or_else(|| malformed("bad parameter index"))
+}
+
+#[must_use]
+fn malformed(reason: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Malformed parameterized string: {}", reason),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expansion() {
+        let sut = |cap: &[u8], params: &[Param]| expand(cap, params).unwrap();
+
+        assert_eq!(
+            sut(b"\x1B[%i%p1%d;%p2%dH", &[0.into(), 0.into()]),
+            b"\x1B[1;1H"
+        );
+        assert_eq!(sut(b"\x1B[%p1%dA", &[12.into()]), b"\x1B[12A");
+        assert_eq!(sut(b"%p1%c", &[65.into()]), b"A");
+        assert_eq!(sut(b"%p1%s-%p1%l%d", &["abc".into()]), b"abc-3");
+        assert_eq!(sut(b"%{10}%{3}%m%d", &[]), b"1");
+        assert_eq!(sut(b"%'a'%d", &[]), b"97");
+        assert_eq!(sut(b"%p1%Pa%ga%ga%+%d", &[21.into()]), b"42");
+        assert_eq!(
+            sut(b"%p1%03d|%p1%:-4d|%p1%x|%p1%#X", &[42.into()]),
+            b"042|42  |2a|0X2A"
+        );
+
+        // From xterm-256color `setaf`.
+        let setaf = b"\x1B[%?%p1%{8}%<%t3%p1%d%e%p1%{16}%<%t9%p1%{8}%-%d%e38;5;%p1%d%;m";
+        assert_eq!(sut(setaf, &[1.into()]), b"\x1B[31m");
+        assert_eq!(sut(setaf, &[9.into()]), b"\x1B[91m");
+        assert_eq!(sut(setaf, &[196.into()]), b"\x1B[38;5;196m");
+
+        // From vt100 `cup` and `clear`, padding is dropped.
+        assert_eq!(
+            sut(b"\x1B[%i%p1%d;%p2%dH$<5>", &[2.into(), 3.into()]),
+            b"\x1B[3;4H"
+        );
+        assert_eq!(sut(b"\x1B[H\x1B[J$<50>", &[]), b"\x1B[H\x1B[J");
+        assert_eq!(sut(b"a$<2.5*/>b", &[]), b"ab");
+        assert_eq!(sut(b"$<>$5$<x>", &[]), b"$<>$5$<x>");
+
+        assert_eq!(sut(b"%p1%99999999999d", &[1.into()]).len(), MAX_WIDTH);
+        assert_eq!(sut(b"%p1%.99999999999d", &[1.into()]).len(), MAX_WIDTH);
+        assert_eq!(
+            sut(b"%p1%:-4294967296s|", &["a".into()]).len(),
+            MAX_WIDTH + 1
+        );
+
+        assert!(expand(b"%d", &[]).is_err());
+        assert!(expand(b"%p0%d", &[]).is_err());
+        assert!(expand(b"%", &[]).is_err());
+    }
+}
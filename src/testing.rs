@@ -0,0 +1,575 @@
+pub mod pty;
+pub mod snapshot;
+
+use crate::printer::{Style, StyledContent};
+use crate::text::ansi::{self, Event};
+use crate::text::{grapheme_width, graphemes};
+use crate::vector::Vector2;
+use crate::{Device, Mode, UpdatePolicy};
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+use std::{mem, str};
+
+const ESC: u8 = 0x1B;
+
+// DEC private modes.
+const AUTOWRAP: u16 = 7;
+const CURSOR_VISIBLE: u16 = 25;
+const ALTERNATE_BUFFER: u16 = 47;
+const ALTERNATE_BUFFER_CLEAR: u16 = 1047;
+const ALTERNATE_BUFFER_SAVE_CURSOR: u16 = 1049;
+
+// A cell of the grid, the cell on the right of a wide character is left
+// empty, blank cells hold a space.
+pub type Cell = StyledContent<String>;
+
+// In-memory terminal emulator understanding the xterm control sequences
+// emitted by `Term`, to be used as both its writer and its device, e.g.
+// `Term::with_writer(vt.clone()).with_device(vt.clone())`. Clones share
+// the same screen, which can be inspected while `Term` is alive as well
+// as once it has been dropped.
+//
+// As on a real terminal, line feeds don't move the cursor to the first
+// column, that's up to the tty driver when output post-processing is on.
+#[derive(Debug, Clone)]
+pub struct VirtualTerm(Rc<RefCell<Emulator>>);
+
+impl VirtualTerm {
+    #[must_use]
+    pub fn new(size: impl Into<Vector2<u16>>) -> Self {
+        Self(Rc::new(RefCell::new(Emulator::new(size.into()))))
+    }
+
+    pub fn feed(&self, bytes: &[u8]) {
+        self.0.borrow_mut().feed(bytes);
+    }
+
+    #[must_use]
+    pub fn size(&self) -> Vector2<u16> {
+        self.0.borrow().size
+    }
+
+    // Rows and columns are added or removed at the bottom and on the right.
+    pub fn resize(&self, size: impl Into<Vector2<u16>>) {
+        self.0.borrow_mut().resize(size.into());
+    }
+
+    #[must_use]
+    pub fn cell(&self, position: impl Into<Vector2<u16>>) -> Option<Cell> {
+        let [x, y] = position.into().into_inner();
+        let emulator = self.0.borrow();
+        emulator
+            .rows
+            .get(usize::from(y))
+            .and_then(|row| row.get(usize::from(x)))
+            .cloned()
+    }
+
+    // Content of the row, trailing blanks included.
+    #[must_use]
+    pub fn row(&self, y: u16) -> Option<String> {
+        let emulator = self.0.borrow();
+        emulator
+            .rows
+            .get(usize::from(y))
+            .map(|row| row.iter().map(|cell| cell.content.as_str()).collect())
+    }
+
+    // Content of the screen, one line per row, without trailing blanks.
+    #[must_use]
+    pub fn text(&self) -> String {
+        let rows = self.size()[1];
+        (0..rows)
+            .filter_map(|y| self.row(y))
+            .map(|row| row.trim_end_matches(' ').to_string())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[must_use]
+    pub fn cursor_position(&self) -> Vector2<u16> {
+        self.0.borrow().cursor
+    }
+
+    #[must_use]
+    pub fn cursor_visible(&self) -> bool {
+        self.dec_mode(CURSOR_VISIBLE)
+    }
+
+    #[must_use]
+    pub fn alternate_buffer(&self) -> bool {
+        self.0.borrow().primary.is_some()
+    }
+
+    // Whether the DEC private mode (e.g. `2004` for bracketed paste) is set.
+    #[must_use]
+    pub fn dec_mode(&self, mode: u16) -> bool {
+        self.0.borrow().modes.contains(&mode)
+    }
+
+    // Style applied to the text that follows.
+    #[must_use]
+    pub fn style(&self) -> Style {
+        self.0.borrow().parser.style()
+    }
+
+    // Replies to the queries received so far (device status reports and
+    // device attributes), to be sent back as input.
+    #[must_use]
+    pub fn take_answers(&self) -> Vec<u8> {
+        mem::take(&mut self.0.borrow_mut().answers)
+    }
+
+    // Last mode applied through `Device::set_mode`.
+    #[must_use]
+    pub fn mode(&self) -> Mode {
+        self.0.borrow().mode
+    }
+}
+
+impl Write for VirtualTerm {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.feed(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Device for VirtualTerm {
+    fn size(&self) -> io::Result<Vector2<u16>> {
+        Ok(VirtualTerm::size(self))
+    }
+
+    fn set_mode(&mut self, mode: Mode, _: UpdatePolicy) -> io::Result<()> {
+        self.0.borrow_mut().mode = mode;
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct Emulator {
+    parser: ansi::Parser,
+    size: Vector2<u16>,
+    rows: Vec<Vec<Cell>>,
+    // Rows of the primary buffer while the alternate one is in use.
+    primary: Option<Vec<Vec<Cell>>>,
+    cursor: Vector2<u16>,
+    saved_cursor: Vector2<u16>,
+    // The last column has been written, the next character wraps first.
+    wrap_pending: bool,
+    modes: Vec<u16>,
+    mode: Mode,
+    answers: Vec<u8>,
+}
+
+impl Emulator {
+    #[must_use]
+    fn new(size: Vector2<u16>) -> Self {
+        let [cols, rows] = size.into_inner();
+        Self {
+            parser: ansi::Parser::new(),
+            size,
+            rows: vec![vec![blank(Style::default()); usize::from(cols)]; usize::from(rows)],
+            primary: None,
+            cursor: [0, 0].into(),
+            saved_cursor: [0, 0].into(),
+            wrap_pending: false,
+            modes: vec![AUTOWRAP, CURSOR_VISIBLE],
+            mode: Mode::Default,
+            answers: Vec::new(),
+        }
+    }
+
+    fn feed(&mut self, bytes: &[u8]) {
+        for event in self.parser.feed(bytes) {
+            match event {
+                // Tabs are left within the text by the parser.
+                Event::Text(text) => {
+                    for (i, chunk) in text.content.split('\t').enumerate() {
+                        if i > 0 {
+                            self.control(b"\t");
+                        }
+                        for grapheme in graphemes(chunk) {
+                            self.print(grapheme, text.style);
+                        }
+                    }
+                }
+                Event::Newline => self.line_feed(),
+                Event::Control(sequence) => self.control(&sequence),
+            }
+        }
+    }
+
+    fn print(&mut self, grapheme: &str, style: Style) {
+        let [cols, rows] = self.size.into_inner();
+        let width = grapheme_width(grapheme) as u16;
+        if cols == 0 || rows == 0 || width > cols {
+            return;
+        }
+
+        // Combining characters and the like join the previous character.
+        if width == 0 {
+            let [x, y] = self.cursor.into_inner();
+            let x = if self.wrap_pending { x + 1 } else { x };
+            let row = &mut self.rows[usize::from(y)];
+            if let Some(cell) = row[..usize::from(x)]
+                .iter_mut()
+                .rfind(|cell| !cell.content.is_empty())
+            {
+                cell.content.push_str(grapheme);
+            }
+            return;
+        }
+
+        if self.wrap_pending || self.cursor[0] + width > cols {
+            if self.modes.contains(&AUTOWRAP) {
+                self.cursor[0] = 0;
+                self.line_feed();
+            } else {
+                self.cursor[0] = cols - width;
+            }
+        }
+
+        let [x, y] = self.cursor.into_inner();
+        let row = &mut self.rows[usize::from(y)];
+        row[usize::from(x)] = StyledContent {
+            style,
+            content: grapheme.to_string(),
+        };
+        if width == 2 {
+            row[usize::from(x) + 1] = StyledContent {
+                style,
+                content: String::new(),
+            };
+        }
+
+        if x + width < cols {
+            self.cursor[0] = x + width;
+        } else {
+            self.cursor[0] = cols - 1;
+            self.wrap_pending = self.modes.contains(&AUTOWRAP);
+        }
+    }
+
+    fn control(&mut self, sequence: &[u8]) {
+        let [x, y] = self.cursor.into_inner();
+        if self.size[0] == 0 || self.size[1] == 0 {
+            return;
+        }
+
+        match sequence {
+            b"\r" => self.move_to(0, y),
+            b"\x08" => self.move_to(x.saturating_sub(1), y),
+            b"\t" => self.move_to((x / 8 + 1).saturating_mul(8), y),
+            [ESC, b'[', ..] => self.csi(sequence),
+            [ESC, b'7'] => self.saved_cursor = self.cursor,
+            [ESC, b'8'] => self.move_to(self.saved_cursor[0], self.saved_cursor[1]),
+            [ESC, b'D'] => self.line_feed(),
+            [ESC, b'E'] => {
+                self.move_to(0, y);
+                self.line_feed();
+            }
+            [ESC, b'M'] if y == 0 => self.scroll_down(0, 1),
+            [ESC, b'M'] => self.move_to(x, y - 1),
+            [ESC, b'c'] => {
+                *self = Self {
+                    mode: self.mode,
+                    ..Self::new(self.size)
+                }
+            }
+            // Bell, character sets, OSC, APC and the like.
+            _ => {}
+        }
+    }
+
+    // From: (https://invisible-island.net/xterm/ctlseqs/ctlseqs.html#h3-Functions-using-CSI-_-ordered-by-the-final-character_s_)
+    fn csi(&mut self, sequence: &[u8]) {
+        let Some((&last, body)) = sequence[2..].split_last() else {
+            return;
+        };
+        let (private, params) = match body {
+            [b'?', params @ ..] => (true, params),
+            _ => (false, body),
+        };
+        // Other private markers and intermediate bytes are not supported.
+        if !params.iter().all(|b| b.is_ascii_digit() || *b == b';') {
+            return;
+        }
+
+        let params = params
+            .split(|b| *b == b';')
+            .map(|p| str::from_utf8(p).ok().and_then(|p| p.parse().ok()))
+            .map(Option::unwrap_or_default)
+            .collect::<Vec<u16>>();
+        // Missing and zero parameters take the default value.
+        let arg =
+            |i: usize, default: u16| params.get(i).copied().filter(|p| *p > 0).unwrap_or(default);
+
+        let [x, y] = self.cursor.into_inner();
+        let [cols, _] = self.size.into_inner();
+
+        match (private, last) {
+            (true, b'h') => params
+                .iter()
+                .for_each(|mode| self.set_dec_mode(*mode, true)),
+            (true, b'l') => params
+                .iter()
+                .for_each(|mode| self.set_dec_mode(*mode, false)),
+            (true, _) => {}
+            (false, b'A') => self.move_to(x, y.saturating_sub(arg(0, 1))),
+            (false, b'B') => self.move_to(x, y.saturating_add(arg(0, 1))),
+            (false, b'C') => self.move_to(x.saturating_add(arg(0, 1)), y),
+            (false, b'D') => self.move_to(x.saturating_sub(arg(0, 1)), y),
+            (false, b'E') => self.move_to(0, y.saturating_add(arg(0, 1))),
+            (false, b'F') => self.move_to(0, y.saturating_sub(arg(0, 1))),
+            (false, b'G') => self.move_to(arg(0, 1) - 1, y),
+            (false, b'd') => self.move_to(x, arg(0, 1) - 1),
+            (false, b'H' | b'f') => self.move_to(arg(1, 1) - 1, arg(0, 1) - 1),
+            (false, b'J') => match params[0] {
+                0 => {
+                    self.erase_row(y, x, cols);
+                    (y + 1..self.size[1]).for_each(|y| self.erase_row(y, 0, cols));
+                }
+                1 => {
+                    (0..y).for_each(|y| self.erase_row(y, 0, cols));
+                    self.erase_row(y, 0, x + 1);
+                }
+                _ => (0..self.size[1]).for_each(|y| self.erase_row(y, 0, cols)),
+            },
+            (false, b'K') => match params[0] {
+                0 => self.erase_row(y, x, cols),
+                1 => self.erase_row(y, 0, x + 1),
+                _ => self.erase_row(y, 0, cols),
+            },
+            (false, b'X') => self.erase_row(y, x, x.saturating_add(arg(0, 1))),
+            (false, b'@') => {
+                let blank = self.blank();
+                let row = &mut self.rows[usize::from(y)];
+                for _ in 0..arg(0, 1).min(cols - x) {
+                    row.insert(usize::from(x), blank.clone());
+                }
+                row.truncate(usize::from(cols));
+            }
+            (false, b'P') => {
+                let blank = self.blank();
+                let row = &mut self.rows[usize::from(y)];
+                for _ in 0..arg(0, 1).min(cols - x) {
+                    row.remove(usize::from(x));
+                    row.push(blank.clone());
+                }
+            }
+            (false, b'L') => self.scroll_down(y, arg(0, 1)),
+            (false, b'M') => self.scroll_up(y, arg(0, 1)),
+            (false, b'S') => self.scroll_up(0, arg(0, 1)),
+            (false, b'T') => self.scroll_down(0, arg(0, 1)),
+            (false, b'n') => match params[0] {
+                5 => self.answers.extend_from_slice(b"\x1B[0n"),
+                6 => self
+                    .answers
+                    .extend_from_slice(format!("\x1B[{};{}R", y + 1, x + 1).as_bytes()),
+                _ => {}
+            },
+            // A VT100 with advanced video option.
+            (false, b'c') if params[0] == 0 => self.answers.extend_from_slice(b"\x1B[?1;2c"),
+            (false, b's') => self.saved_cursor = self.cursor,
+            (false, b'u') => self.move_to(self.saved_cursor[0], self.saved_cursor[1]),
+            _ => {}
+        }
+    }
+
+    fn set_dec_mode(&mut self, mode: u16, enabled: bool) {
+        match (mode, enabled) {
+            (ALTERNATE_BUFFER | ALTERNATE_BUFFER_CLEAR | ALTERNATE_BUFFER_SAVE_CURSOR, true)
+                if self.primary.is_none() =>
+            {
+                if mode == ALTERNATE_BUFFER_SAVE_CURSOR {
+                    self.saved_cursor = self.cursor;
+                }
+                let blank = vec![blank(Style::default()); usize::from(self.size[0])];
+                let alternate = vec![blank; usize::from(self.size[1])];
+                self.primary = Some(mem::replace(&mut self.rows, alternate));
+            }
+            (ALTERNATE_BUFFER | ALTERNATE_BUFFER_CLEAR | ALTERNATE_BUFFER_SAVE_CURSOR, false) => {
+                if let Some(primary) = self.primary.take() {
+                    self.rows = primary;
+                }
+                if mode == ALTERNATE_BUFFER_SAVE_CURSOR {
+                    self.move_to(self.saved_cursor[0], self.saved_cursor[1]);
+                }
+            }
+            _ => {}
+        }
+
+        self.modes.retain(|m| *m != mode);
+        if enabled {
+            self.modes.push(mode);
+        }
+    }
+
+    fn move_to(&mut self, x: u16, y: u16) {
+        let [cols, rows] = self.size.into_inner();
+        self.cursor = [x.min(cols.saturating_sub(1)), y.min(rows.saturating_sub(1))].into();
+        self.wrap_pending = false;
+    }
+
+    fn line_feed(&mut self) {
+        let [x, y] = self.cursor.into_inner();
+        if y + 1 >= self.size[1] {
+            self.scroll_up(0, 1);
+            self.move_to(x, y);
+        } else {
+            self.move_to(x, y + 1);
+        }
+    }
+
+    // Moves the rows from `top` down up by `n`, blanking the bottom ones.
+    fn scroll_up(&mut self, top: u16, n: u16) {
+        let blank = vec![self.blank(); usize::from(self.size[0])];
+        for _ in 0..n.min(self.size[1].saturating_sub(top)) {
+            self.rows.remove(usize::from(top));
+            self.rows.push(blank.clone());
+        }
+    }
+
+    // Moves the rows from `top` down down by `n`, blanking the top ones.
+    fn scroll_down(&mut self, top: u16, n: u16) {
+        let blank = vec![self.blank(); usize::from(self.size[0])];
+        for _ in 0..n.min(self.size[1].saturating_sub(top)) {
+            self.rows.pop();
+            self.rows.insert(usize::from(top), blank.clone());
+        }
+    }
+
+    fn erase_row(&mut self, y: u16, start: u16, end: u16) {
+        let blank = self.blank();
+        if let Some(row) = self.rows.get_mut(usize::from(y)) {
+            let end = usize::from(end).min(row.len());
+            let start = usize::from(start).min(end);
+            row[start..end].fill(blank);
+        }
+    }
+
+    fn resize(&mut self, size: Vector2<u16>) {
+        let [cols, rows] = size.into_inner();
+        let resize = |grid: &mut Vec<Vec<Cell>>| {
+            let blank = blank(Style::default());
+            grid.resize(usize::from(rows), vec![blank.clone(); usize::from(cols)]);
+            grid.iter_mut()
+                .for_each(|row| row.resize(usize::from(cols), blank.clone()));
+        };
+
+        resize(&mut self.rows);
+        if let Some(primary) = self.primary.as_mut() {
+            resize(primary);
+        }
+        self.size = size;
+        self.move_to(self.cursor[0], self.cursor[1]);
+    }
+
+    // Erased cells take the current background, as xterm does.
+    #[must_use]
+    fn blank(&self) -> Cell {
+        blank(Style::default().with_background(self.parser.style().background))
+    }
+}
+
+#[must_use]
+fn blank(style: Style) -> Cell {
+    StyledContent {
+        style,
+        content: " ".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::printer::{Color, FontWeight, Styled};
+    use crate::screen::Buffer;
+    use crate::Term;
+
+    #[test]
+    fn rendering() {
+        let vt = VirtualTerm::new([10, 4]);
+        let mut term = Term::with_writer(vt.clone()).with_device(vt.clone());
+
+        term.flow()
+            .set_mode(Mode::Raw)
+            .screen()
+            .set_buffer(Buffer::Alternate)
+            .cursor()
+            .hide()
+            .set_position([3, 2])
+            .printer()
+            .print(
+                "Hi".with_foreground(Color::Green)
+                    .with_weight(FontWeight::Bold),
+            )
+            .print(" 漢")
+            .flush()
+            .unwrap();
+
+        let cell = vt.cell([3, 2]).unwrap();
+        assert_eq!(cell.content, "H");
+        assert_eq!(
+            cell.style,
+            Style::foreground(Color::Green).with_weight(FontWeight::Bold)
+        );
+        assert_eq!(vt.row(2).unwrap(), "   Hi 漢  ");
+        assert_eq!(vt.cell([7, 2]).unwrap().content, "");
+        assert_eq!(vt.cursor_position(), [8, 2].into());
+        assert!(vt.alternate_buffer());
+        assert!(!vt.cursor_visible());
+        assert_eq!(vt.mode(), Mode::Raw);
+
+        drop(term);
+        assert!(!vt.alternate_buffer());
+        assert!(vt.cursor_visible());
+        assert_eq!(vt.mode(), Mode::Default);
+        assert_eq!(vt.style(), Style::default());
+        assert_eq!(vt.text(), "\n\n\n");
+    }
+
+    #[test]
+    fn editing() {
+        let vt = VirtualTerm::new([4, 3]);
+        vt.feed(b"abcdef\r\n\x1B[41mgh\x1B[m");
+        assert_eq!(vt.text(), "abcd\nef\ngh");
+        assert_eq!(
+            vt.cell([0, 2]).unwrap().style,
+            Style::background(Color::Red)
+        );
+        assert_eq!(vt.cursor_position(), [2, 2].into());
+
+        vt.feed(b"\n");
+        assert_eq!(vt.text(), "ef\ngh\n");
+        assert_eq!(vt.cursor_position(), [2, 2].into());
+
+        vt.feed(b"\x1B[1;2H\x1B[K\x1B[2;1H\x1B[P\x1B[L");
+        assert_eq!(vt.text(), "e\n\nh");
+        assert!(vt.row(3).is_none());
+
+        let mut term = Term::with_writer(vt.clone()).with_device(vt.clone());
+        vt.resize([6, 2]);
+        assert_eq!(term.size().unwrap(), [6, 2].into());
+        assert_eq!(vt.text(), "e\n");
+
+        term.screen().clear().flush().unwrap();
+        assert_eq!(vt.text(), "\n");
+
+        vt.feed(b"ab\x1B[6n\x1B[c\x1B[5n");
+        assert_eq!(vt.take_answers(), b"\x1B[1;3R\x1B[?1;2c\x1B[0n");
+        assert!(vt.take_answers().is_empty());
+
+        let vt = VirtualTerm::new([12, 1]);
+        vt.feed(b"a\tb");
+        assert_eq!(vt.cursor_position(), [9, 0].into());
+        vt.feed(b"\tc\td");
+        assert_eq!(vt.row(0).unwrap(), "a       b  d");
+    }
+}
@@ -0,0 +1,297 @@
+use crate::testing::VirtualTerm;
+use crate::vector::Vector2;
+
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::ptr;
+use std::time::{Duration, Instant};
+
+use libc::{c_int, winsize, TIOCSCTTY, TIOCSWINSZ};
+use termios::Termios;
+
+// Runs a process on a pseudo-terminal, rendering its output on a virtual
+// terminal, which also answers its queries (e.g. `Cursor::position`).
+// The process is killed, if still running, once dropped.
+pub struct Pty {
+    master: File,
+    child: Child,
+    vt: VirtualTerm,
+}
+
+impl Pty {
+    // Spawns `command` with the standard streams on a new pty of the given
+    // size, which becomes its controlling terminal.
+    pub fn spawn(mut command: Command, size: impl Into<Vector2<u16>>) -> io::Result<Self> {
+        let size = size.into();
+        let (mut master, mut slave) = (0, 0);
+        let win = make_winsize(size);
+
+        if unsafe { libc::openpty(&mut master, &mut slave, ptr::null_mut(), ptr::null(), &win) }
+            == -1
+        {
+            return Err(io::Error::last_os_error());
+        }
+
+        let (master, slave) =
+            unsafe { (OwnedFd::from_raw_fd(master), OwnedFd::from_raw_fd(slave)) };
+        for fd in [&master, &slave] {
+            if unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC) } == -1 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        command
+            .stdin(Stdio::from(slave.try_clone()?))
+            .stdout(Stdio::from(slave.try_clone()?))
+            .stderr(Stdio::from(slave));
+
+        // The standard streams are already in place at this point.
+        unsafe {
+            command.pre_exec(|| {
+                if libc::setsid() == -1 || libc::ioctl(0, TIOCSCTTY, 0) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            })
+        };
+
+        // Spawning drops the copies of the slave held by `command`, so that
+        // reading the master fails once the process is gone.
+        let child = command.spawn()?;
+        drop(command);
+
+        Ok(Self {
+            master: File::from(master),
+            child,
+            vt: VirtualTerm::new(size),
+        })
+    }
+
+    pub fn vt(&self) -> &VirtualTerm {
+        &self.vt
+    }
+
+    pub fn id(&self) -> u32 {
+        self.child.id()
+    }
+
+    // Settings of the pty, as applied by the process.
+    pub fn termios(&self) -> io::Result<Termios> {
+        Termios::from_fd(self.master.as_raw_fd())
+    }
+
+    // Writes `input` as if typed on the keyboard.
+    pub fn send(&mut self, input: &[u8]) -> io::Result<()> {
+        self.master.write_all(input)?;
+        self.master.flush()
+    }
+
+    // Sends each input once the output has been read for the given delay.
+    pub fn play<'i>(
+        &mut self,
+        script: impl IntoIterator<Item = (Duration, &'i [u8])>,
+    ) -> io::Result<()> {
+        script.into_iter().try_for_each(|(delay, input)| {
+            self.read_for(delay)?;
+            self.send(input)
+        })
+    }
+
+    // The process gets notified through SIGWINCH.
+    pub fn resize(&mut self, size: impl Into<Vector2<u16>>) -> io::Result<()> {
+        let size = size.into();
+        if unsafe { libc::ioctl(self.master.as_raw_fd(), TIOCSWINSZ, &make_winsize(size)) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        self.vt.resize(size);
+        Ok(())
+    }
+
+    // Reads the output for the whole `duration`, or until the process exits.
+    pub fn read_for(&mut self, duration: Duration) -> io::Result<()> {
+        let deadline = Instant::now() + duration;
+        while let Some(timeout) = remaining(deadline) {
+            if !self.read(timeout)? {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    // Reads the output until `predicate` holds for the virtual terminal,
+    // failing with `TimedOut` if it doesn't within `timeout`.
+    pub fn wait_until<F>(&mut self, timeout: Duration, mut predicate: F) -> io::Result<()>
+    where
+        F: FnMut(&VirtualTerm) -> bool,
+    {
+        let deadline = Instant::now() + timeout;
+        while !predicate(&self.vt) {
+            match remaining(deadline) {
+                Some(timeout) if self.read(timeout)? => {}
+                _ => return Err(make_err("Unable to wait: condition not met")),
+            }
+        }
+        Ok(())
+    }
+
+    // Reads the output until the process exits, failing with `TimedOut`
+    // if it doesn't within `timeout`.
+    pub fn wait(&mut self, timeout: Duration) -> io::Result<ExitStatus> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            match remaining(deadline) {
+                Some(timeout) if self.read(timeout)? => {}
+                Some(_) => return self.child.wait(),
+                None => return Err(make_err("Unable to wait: process still running")),
+            }
+        }
+    }
+
+    // Feeds the output available within `timeout` to the virtual terminal,
+    // sending back its answers, returns `false` once the pty has been closed
+    // on the other side.
+    fn read(&mut self, timeout: Duration) -> io::Result<bool> {
+        let mut poll = libc::pollfd {
+            fd: self.master.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let millis = c_int::try_from(timeout.as_millis()).unwrap_or(c_int::MAX);
+
+        match unsafe { libc::poll(&mut poll, 1, millis) } {
+            -1 => {
+                let err = io::Error::last_os_error();
+                return match err.kind() {
+                    io::ErrorKind::Interrupted => Ok(true),
+                    _ => Err(err),
+                };
+            }
+            0 => return Ok(true),
+            _ => {}
+        }
+
+        let mut buf = [0; 4096];
+        match self.master.read(&mut buf) {
+            Ok(0) => Ok(false),
+            Ok(n) => {
+                self.vt.feed(&buf[..n]);
+                let answers = self.vt.take_answers();
+                if !answers.is_empty() {
+                    self.send(&answers)?;
+                }
+                Ok(true)
+            }
+            // Linux reports EIO once every copy of the slave has been closed.
+            Err(e) if e.raw_os_error() == Some(libc::EIO) => Ok(false),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => Ok(true),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl Drop for Pty {
+    fn drop(&mut self) {
+        if let Ok(None) = self.child.try_wait() {
+            crate::best_effort(self.child.kill());
+            crate::best_effort(self.child.wait());
+        }
+    }
+}
+
+#[must_use]
+fn make_winsize(size: Vector2<u16>) -> winsize {
+    winsize {
+        ws_col: size[0],
+        ws_row: size[1],
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    }
+}
+
+#[must_use]
+fn remaining(deadline: Instant) -> Option<Duration> {
+    let now = Instant::now();
+    (now < deadline).then(|| deadline - now)
+}
+
+fn make_err(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::TimedOut, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nio::ReadNonblock;
+    use crate::screen::Buffer;
+    use crate::{Mode, Term};
+
+    use std::env;
+    use termios::ICANON;
+
+    const CHILD_ENV: &str = "TERMULAR_PTY_CHILD";
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    // Runs in the child spawned by `term`, does nothing otherwise.
+    #[test]
+    fn child() {
+        if env::var_os(CHILD_ENV).is_none() {
+            return;
+        }
+
+        let (stdout, stderr) = (io::stdout(), io::stderr());
+        let mut term = Term::open(stdout.lock(), stderr.lock()).unwrap();
+        term.flow()
+            .set_mode(Mode::Raw)
+            .screen()
+            .set_buffer(Buffer::Alternate)
+            .cursor()
+            .hide()
+            .set_position([4, 1])
+            .printer()
+            .print("ready")
+            .flush()
+            .unwrap();
+
+        let position = term.cursor().position().unwrap();
+        term.printer()
+            .print(format!(" at {}", position))
+            .flush()
+            .unwrap();
+
+        let mut key = [0];
+        while term.stdin_mut().read_timeout(&mut key, TIMEOUT).unwrap() == 0 {}
+        let size = term.size().unwrap();
+        drop(term);
+
+        println!("\n{} pressed on {}", key[0] as char, size);
+    }
+
+    #[test]
+    fn term() {
+        let mut command = Command::new(env::current_exe().unwrap());
+        command
+            .args(["testing::pty::tests::child", "--exact", "--nocapture"])
+            .env(CHILD_ENV, "1");
+
+        let mut pty = Pty::spawn(command, [40, 10]).unwrap();
+        pty.wait_until(TIMEOUT, |vt| vt.text().contains("ready at [9, 1]"))
+            .unwrap();
+
+        assert!(pty.vt().alternate_buffer());
+        assert!(!pty.vt().cursor_visible());
+        assert_eq!(pty.termios().unwrap().c_lflag & ICANON, 0);
+
+        pty.resize([50, 12]).unwrap();
+        pty.play([(Duration::from_millis(100), b"q".as_slice())])
+            .unwrap();
+        assert!(pty.wait(TIMEOUT).unwrap().success());
+
+        assert!(!pty.vt().alternate_buffer());
+        assert!(pty.vt().cursor_visible());
+        assert!(pty.vt().text().contains("q pressed on [50, 12]"));
+        assert_ne!(pty.termios().unwrap().c_lflag & ICANON, 0);
+    }
+}
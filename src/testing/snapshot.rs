@@ -0,0 +1,232 @@
+use crate::printer::{Color, FontWeight, Style, TextDecoration};
+use crate::testing::VirtualTerm;
+use crate::text::markup;
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Set to `1` to write the snapshots instead of comparing against them.
+pub const UPDATE_ENV: &str = "TERMULAR_UPDATE_SNAPSHOTS";
+
+// Compares against `tests/snapshots/<name>.snap` in the calling crate.
+#[macro_export]
+macro_rules! assert_snapshot {
+    ($name:expr, $actual:expr $(,)?) => {
+        $crate::testing::snapshot::assert_snapshot(
+            ::std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("tests/snapshots")
+                .join(format!("{}.snap", $name)),
+            &$actual,
+        )
+    };
+}
+
+// Rows of the screen with the styles written as markup tags, e.g.
+// `[bold green]Hi[/] there`, trailing blanks are dropped unless styled.
+#[must_use]
+pub fn styled(vt: &VirtualTerm) -> String {
+    let [cols, rows] = vt.size().into_inner();
+
+    (0..rows)
+        .map(|y| {
+            let cells = (0..cols)
+                .filter_map(|x| vt.cell([x, y]))
+                .collect::<Vec<_>>();
+            let end = cells
+                .iter()
+                .rposition(|cell| cell.content != " " || cell.style != Style::default())
+                .map_or(0, |i| i + 1);
+
+            let mut row = String::new();
+            for run in cells[..end].chunk_by(|a, b| a.style == b.style) {
+                let content = run
+                    .iter()
+                    .map(|cell| cell.content.as_str())
+                    .collect::<String>();
+                match attributes(run[0].style) {
+                    attributes if attributes.is_empty() => row += &markup::escape(&content),
+                    attributes => {
+                        row += &format!("[{}]{}[/]", attributes, markup::escape(&content))
+                    }
+                }
+            }
+            row
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Panics showing the differences when `actual` doesn't match the snapshot
+// at `path`, or the latter doesn't exist, unless `TERMULAR_UPDATE_SNAPSHOTS`
+// is set, in which case the snapshot is (re)written.
+#[track_caller]
+pub fn assert_snapshot(path: impl AsRef<Path>, actual: &str) {
+    let path = path.as_ref();
+
+    if env::var(UPDATE_ENV).is_ok_and(|value| value == "1") {
+        if let Err(e) = write(path, actual) {
+            panic!("Unable to write snapshot `{}`: {}", path.display(), e);
+        }
+        return;
+    }
+
+    match fs::read_to_string(path) {
+        Ok(expected) if expected.strip_suffix('\n') == Some(actual) => {}
+        Ok(expected) => panic!(
+            "Snapshot `{}` doesn't match, rerun with {}=1 to update it\n--- expected\n+++ actual\n{}",
+            path.display(),
+            UPDATE_ENV,
+            diff(expected.strip_suffix('\n').unwrap_or(&expected), actual)
+        ),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => panic!(
+            "Snapshot `{}` not found, rerun with {}=1 to create it\n{}",
+            path.display(),
+            UPDATE_ENV,
+            actual
+        ),
+        Err(e) => panic!("Unable to read snapshot `{}`: {}", path.display(), e),
+    }
+}
+
+// Attributes of the markup tag styling text as `style`, empty for the
+// default style.
+#[must_use]
+fn attributes(style: Style) -> String {
+    let weight = match style.weight {
+        FontWeight::Normal => None,
+        FontWeight::Light => Some("light".to_string()),
+        FontWeight::Bold => Some("bold".to_string()),
+    };
+    let decoration = match style.decoration {
+        TextDecoration::None => None,
+        TextDecoration::Strike => Some("strike".to_string()),
+        TextDecoration::Underline => Some("underline".to_string()),
+    };
+    let foreground = (style.foreground != Color::Default).then(|| color_name(style.foreground));
+    let background = (style.background != Color::Default)
+        .then(|| format!("on {}", color_name(style.background)));
+
+    [weight, decoration, foreground, background]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[must_use]
+fn color_name(color: Color) -> String {
+    match color {
+        Color::Default => "default".to_string(),
+        Color::Black => "black".to_string(),
+        Color::Red => "red".to_string(),
+        Color::Green => "green".to_string(),
+        Color::Yellow => "yellow".to_string(),
+        Color::Blue => "blue".to_string(),
+        Color::Magenta => "magenta".to_string(),
+        Color::Cyan => "cyan".to_string(),
+        Color::White => "white".to_string(),
+        Color::Rgb(r, g, b) => format!("#{:02x}{:02x}{:02x}", r, g, b),
+    }
+}
+
+fn write(path: &Path, content: &str) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, format!("{}\n", content))
+}
+
+// Lines removed from `expected` are marked with `-`, the ones added in
+// `actual` with `+`, following the longest common subsequence.
+#[must_use]
+pub fn diff(expected: &str, actual: &str) -> String {
+    let old = expected.split('\n').collect::<Vec<_>>();
+    let new = actual.split('\n').collect::<Vec<_>>();
+
+    // Lengths of the longest common subsequences of the suffixes.
+    let mut lcs = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push(format!("  {}", old[i]));
+            (i, j) = (i + 1, j + 1);
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            lines.push(format!("- {}", old[i]));
+            i += 1;
+        } else {
+            lines.push(format!("+ {}", new[j]));
+            j += 1;
+        }
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::printer::Styled;
+    use crate::Term;
+
+    #[test]
+    fn styling() {
+        let vt = VirtualTerm::new([12, 3]);
+        vt.feed(b"[a]\x1B[1;32mHi\x1B[m \x1B[44m  \x1B[m\r\n\n\\");
+
+        assert_eq!(vt.text(), "[a]Hi\n\n\\");
+        assert_eq!(styled(&vt), "\\[a][bold green]Hi[/] [on blue]  [/]\n\n\\\\");
+    }
+
+    #[test]
+    fn attributing() {
+        let style = Style::foreground(Color::Rgb(0xFF, 0x80, 0))
+            .with_background(Color::Blue)
+            .with_weight(FontWeight::Bold)
+            .with_decoration(TextDecoration::Underline);
+
+        assert_eq!(attributes(Style::new()), "");
+        assert_eq!(attributes(style), "bold underline #ff8000 on blue");
+
+        let text = markup::parse(&format!("[{}]a", attributes(style))).unwrap();
+        assert_eq!(text.lines[0].spans[0].style, style);
+    }
+
+    #[test]
+    fn diffing() {
+        assert_eq!(diff("a\nb\nc", "a\nb\nc"), "  a\n  b\n  c");
+        assert_eq!(diff("a\nb\nc", "a\nx\nc\nd"), "  a\n- b\n+ x\n  c\n+ d");
+        assert_eq!(diff("", "a"), "- \n+ a");
+    }
+
+    #[test]
+    fn snapshots() {
+        let vt = VirtualTerm::new([16, 4]);
+        let mut term = Term::with_writer(vt.clone()).with_device(vt.clone());
+
+        term.screen()
+            .clear()
+            .printer()
+            .print("Status".with_weight(FontWeight::Bold))
+            .cursor()
+            .set_position([2, 2])
+            .printer()
+            .print("OK".with_foreground(Color::Green))
+            .print(" 3 files")
+            .flush()
+            .unwrap();
+
+        crate::assert_snapshot!("testing-snapshots", styled(&vt));
+    }
+}
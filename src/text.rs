@@ -0,0 +1,130 @@
+pub mod ansi;
+pub mod layout;
+pub mod markup;
+mod rich;
+
+pub use rich::{Line, Span, Text};
+
+use crate::printer::StyledContent;
+
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthChar;
+
+const ZERO_WIDTH_JOINER: char = '\u{200D}';
+const EMOJI_PRESENTATION: char = '\u{FE0F}';
+const TEXT_PRESENTATION: char = '\u{FE0E}';
+
+// Number of columns occupied once printed on the terminal.
+pub trait Width {
+    #[must_use]
+    fn width(&self) -> usize;
+}
+
+impl Width for str {
+    fn width(&self) -> usize {
+        graphemes(self).map(grapheme_width).sum()
+    }
+}
+
+impl Width for String {
+    fn width(&self) -> usize {
+        self.as_str().width()
+    }
+}
+
+impl Width for char {
+    fn width(&self) -> usize {
+        char_width(*self)
+    }
+}
+
+impl<T: Width + ?Sized> Width for &T {
+    fn width(&self) -> usize {
+        (**self).width()
+    }
+}
+
+impl<T: Width + ?Sized> Width for &mut T {
+    fn width(&self) -> usize {
+        (**self).width()
+    }
+}
+
+// Escape sequences setting the style take no room on the screen.
+impl<T: Width> Width for StyledContent<T> {
+    fn width(&self) -> usize {
+        self.content.width()
+    }
+}
+
+// From: (https://unicode.org/reports/tr29/#Grapheme_Cluster_Boundaries)
+// Extended grapheme clusters, the user-perceived characters.
+pub fn graphemes(s: &str) -> impl DoubleEndedIterator<Item = &str> {
+    UnicodeSegmentation::graphemes(s, true)
+}
+
+// Terminals draw a whole cluster in the cells of its first character,
+// combining marks and the like don't take further room, while emoji
+// sequences (e.g. joined through ZWJ or with the emoji presentation
+// selector) are drawn as a single wide character.
+#[must_use]
+pub fn grapheme_width(grapheme: &str) -> usize {
+    let mut chars = grapheme.chars();
+    let Some(first) = chars.next() else {
+        return 0;
+    };
+
+    let width = char_width(first);
+    let mut rest = chars.clone();
+
+    if rest.any(|c| c == TEXT_PRESENTATION) {
+        width.min(1)
+    } else if chars.any(|c| c == EMOJI_PRESENTATION || c == ZERO_WIDTH_JOINER)
+        || is_regional_indicator(first)
+    {
+        2
+    } else {
+        width.min(2)
+    }
+}
+
+// Ambiguous width characters are treated as narrow, as most terminals do
+// outside of CJK locales, control characters take no room at all.
+#[must_use]
+fn char_width(c: char) -> usize {
+    UnicodeWidthChar::width(c).unwrap_or(0)
+}
+
+#[must_use]
+fn is_regional_indicator(c: char) -> bool {
+    ('\u{1F1E6}'..='\u{1F1FF}').contains(&c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::printer::{Color, Styled};
+
+    #[test]
+    fn widths() {
+        assert_eq!("".width(), 0);
+        assert_eq!("Hello world".width(), 11);
+        assert_eq!("こんにちは".width(), 10);
+        assert_eq!("e\u{301}".width(), 1);
+        assert_eq!("\u{1F600}".width(), 2);
+        assert_eq!("\u{2764}".width(), 1);
+        assert_eq!("\u{2764}\u{FE0F}".width(), 2);
+        assert_eq!("\u{1F44D}\u{1F3FD}".width(), 2);
+        assert_eq!("\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}".width(), 2);
+        assert_eq!("\u{1F1EE}\u{1F1F9}".width(), 2);
+        assert_eq!("a\tb\x1B".width(), 2);
+        assert_eq!(Width::width(&'漢'), 2);
+        assert_eq!("漢字".with_foreground(Color::Red).width(), 4);
+    }
+
+    #[test]
+    fn segmentation() {
+        let sut = graphemes("e\u{301}\u{1F468}\u{200D}\u{1F469}\r\nx").collect::<Vec<_>>();
+        assert_eq!(sut, ["e\u{301}", "\u{1F468}\u{200D}\u{1F469}", "\r\n", "x"]);
+    }
+}
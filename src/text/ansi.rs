@@ -0,0 +1,475 @@
+use crate::printer::{Color, FontWeight, Style, StyledContent, TextDecoration};
+use crate::text::{Line, Text, Width};
+
+use std::borrow::Cow;
+use std::mem;
+use std::str;
+
+const ESC: u8 = 0x1B;
+const BEL: u8 = 0x07;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    Text(StyledContent<String>),
+    Newline,
+    // Control characters and escape sequences other than SGR, verbatim.
+    Control(Vec<u8>),
+}
+
+// Turns a byte stream, possibly split at arbitrary points across several
+// reads, into styled text, keeping track of the style set by SGR sequences.
+// Invalid UTF-8 is replaced by U+FFFD.
+#[derive(Debug, Default, Clone)]
+pub struct Parser {
+    style: Style,
+    pending: Vec<u8>,
+}
+
+impl Parser {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Style in effect for the text that follows.
+    #[must_use]
+    pub fn style(&self) -> Style {
+        self.style
+    }
+
+    // Incomplete sequences and characters at the end of `bytes` are kept
+    // until the next call.
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<Event> {
+        let mut data = mem::take(&mut self.pending);
+        data.extend_from_slice(bytes);
+
+        let mut events = Vec::new();
+        let mut run = Vec::new();
+        let mut i = 0;
+
+        while i < data.len() {
+            match data[i] {
+                ESC => {
+                    let Some(len) = sequence_len(&data[i..]) else {
+                        break;
+                    };
+                    let sequence = &data[i..i + len];
+                    match sgr_params(sequence) {
+                        Some(params) => {
+                            let style = apply_sgr(self.style, params);
+                            if style != self.style {
+                                self.flush(&mut run, &mut events, false);
+                                self.style = style;
+                            }
+                        }
+                        None => {
+                            self.flush(&mut run, &mut events, false);
+                            events.push(Event::Control(sequence.to_vec()));
+                        }
+                    }
+                    i += len;
+                }
+                b'\n' => {
+                    self.flush(&mut run, &mut events, false);
+                    events.push(Event::Newline);
+                    i += 1;
+                }
+                c if is_control(c) => {
+                    self.flush(&mut run, &mut events, false);
+                    events.push(Event::Control(vec![c]));
+                    i += 1;
+                }
+                c => {
+                    run.push(c);
+                    i += 1;
+                }
+            }
+        }
+
+        self.pending = data.split_off(i);
+        self.flush(&mut run, &mut events, true);
+        events
+    }
+
+    // Flushes whatever is left at the end of the stream.
+    pub fn finish(&mut self) -> Vec<Event> {
+        let pending = mem::take(&mut self.pending);
+        let mut events = Vec::new();
+
+        if pending.first() == Some(&ESC) {
+            events.push(Event::Control(pending));
+        } else {
+            let mut run = pending;
+            self.flush(&mut run, &mut events, false);
+        }
+        events
+    }
+
+    // When `partial` is set, a character cut at the end of `run` is moved
+    // back to the pending bytes.
+    fn flush(&mut self, run: &mut Vec<u8>, events: &mut Vec<Event>, partial: bool) {
+        if partial {
+            if let Err(e) = str::from_utf8(run) {
+                if e.error_len().is_none() {
+                    let mut tail = run.split_off(e.valid_up_to());
+                    tail.append(&mut self.pending);
+                    self.pending = tail;
+                }
+            }
+        }
+
+        if !run.is_empty() {
+            events.push(Event::Text(StyledContent {
+                style: self.style,
+                content: String::from_utf8_lossy(run).into_owned(),
+            }));
+            run.clear();
+        }
+    }
+}
+
+// Parses the whole byte stream, dropping any control sequence but SGR.
+// A `\r` starts the line over, the text printed before is dropped rather
+// than partly overwritten, and tabs are expanded to spaces up to the next
+// tab stop. Other control characters (BEL, BS, VT, FF, ...) are dropped.
+#[must_use]
+pub fn parse(bytes: &[u8]) -> Text<'static> {
+    let mut parser = Parser::new();
+    let mut events = parser.feed(bytes);
+    events.extend(parser.finish());
+
+    let mut text = Text::new();
+    let mut line = Line::new();
+    // Deferred, so that a `\r\n` line ending keeps the line.
+    let mut carriage_return = false;
+    for event in events {
+        match event {
+            Event::Text(mut span) => {
+                if mem::take(&mut carriage_return) {
+                    line = Line::new();
+                }
+                span.content = expand_tabs(&span.content, line.width());
+                line.push(span);
+            }
+            Event::Newline => {
+                carriage_return = false;
+                text.push(mem::take(&mut line));
+            }
+            Event::Control(control) => carriage_return |= control == b"\r",
+        }
+    }
+    text.push(line);
+    text
+}
+
+// Tab stops are set every 8 columns, as terminals do by default.
+const TAB_WIDTH: usize = 8;
+
+// Replaces the tabs of `s`, printed at `column`, by spaces.
+#[must_use]
+fn expand_tabs(s: &str, mut column: usize) -> String {
+    let mut out = String::with_capacity(s.len());
+    for (i, chunk) in s.split('\t').enumerate() {
+        if i > 0 {
+            let spaces = TAB_WIDTH - column % TAB_WIDTH;
+            out.extend(std::iter::repeat_n(' ', spaces));
+            column += spaces;
+        }
+        out.push_str(chunk);
+        column += chunk.width();
+    }
+    out
+}
+
+// Removes the escape sequences, an incomplete one at the end included.
+// Control characters such as `\r` and `\t` are kept.
+#[must_use]
+pub fn strip_ansi(s: &str) -> Cow<'_, str> {
+    if !s.contains('\x1B') {
+        return s.into();
+    }
+
+    let mut out = String::with_capacity(s.len());
+    let mut start = 0;
+    while let Some(offset) = s[start..].find('\x1B') {
+        let i = start + offset;
+        out.push_str(&s[start..i]);
+        start = sequence_len(&s.as_bytes()[i..]).map_or(s.len(), |len| i + len);
+    }
+    out.push_str(&s[start..]);
+    out.into()
+}
+
+// Number of columns occupied once printed, by the widest line. Escape
+// sequences take no room, carriage returns and tabs are handled as by
+// `parse`.
+#[must_use]
+pub fn visible_width(s: &str) -> usize {
+    if s.bytes().any(|b| b < 0x20 || b == 0x7F) {
+        parse(s.as_bytes()).width()
+    } else {
+        s.width()
+    }
+}
+
+// From: (https://invisible-island.net/xterm/ctlseqs/ctlseqs.html#h2-Definitions)
+// Length of the escape sequence at the start of `bytes`, `None` if it's
+// incomplete. Control strings (OSC, DCS, SOS, PM and APC) end with ST,
+// or BEL as many terminals accept it too.
+#[must_use]
+pub(crate) fn sequence_len(bytes: &[u8]) -> Option<usize> {
+    match bytes.get(1)? {
+        b'[' => {
+            let end = 2 + bytes[2..].iter().position(|b| !(0x20..=0x3F).contains(b))?;
+            // A malformed sequence ends before the unexpected byte.
+            Some(end + usize::from((0x40..=0x7E).contains(&bytes[end])))
+        }
+        b']' | b'P' | b'X' | b'^' | b'_' => {
+            (2..bytes.len()).find_map(|i| match (bytes[i], bytes.get(i + 1)) {
+                (BEL, _) => Some(i + 1),
+                (ESC, Some(b'\\')) => Some(i + 2),
+                _ => None,
+            })
+        }
+        _ => {
+            let end = 1 + bytes[1..].iter().position(|b| !(0x20..=0x2F).contains(b))?;
+            Some(end + usize::from((0x30..=0x7E).contains(&bytes[end])))
+        }
+    }
+}
+
+#[must_use]
+fn is_control(c: u8) -> bool {
+    (c < 0x20 && c != b'\t') || c == 0x7F
+}
+
+// Parameters of a CSI sequence ending with `m`, without private markers
+// or intermediate bytes.
+#[must_use]
+fn sgr_params(sequence: &[u8]) -> Option<&[u8]> {
+    match sequence {
+        [ESC, b'[', params @ .., b'm']
+            if params
+                .iter()
+                .all(|b| b.is_ascii_digit() || *b == b';' || *b == b':') =>
+        {
+            Some(params)
+        }
+        _ => None,
+    }
+}
+
+// From: (https://invisible-island.net/xterm/ctlseqs/ctlseqs.html#h4-Functions-using-CSI-_-ordered-by-the-final-character-lparen-s-rparen:CSI-Pm-m.1CA7)
+// Unsupported attributes are ignored, bright colors are mapped to the
+// corresponding normal ones.
+#[must_use]
+fn apply_sgr(mut style: Style, params: &[u8]) -> Style {
+    let groups = params
+        .split(|b| *b == b';')
+        .map(|group| group.split(|b| *b == b':').map(number).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+
+    let mut i = 0;
+    while i < groups.len() {
+        let group = &groups[i];
+        i += 1;
+
+        match group[0] {
+            0 => style = Style::default(),
+            1 => style.weight = FontWeight::Bold,
+            2 => style.weight = FontWeight::Light,
+            22 => style.weight = FontWeight::Normal,
+            4 => style.decoration = TextDecoration::Underline,
+            9 => style.decoration = TextDecoration::Strike,
+            24 | 29 => style.decoration = TextDecoration::None,
+            n @ (30..=37 | 90..=97) => style.foreground = BASIC[usize::from(n % 10)],
+            n @ (40..=47 | 100..=107) => style.background = BASIC[usize::from(n % 10)],
+            39 => style.foreground = Color::Default,
+            49 => style.background = Color::Default,
+            n @ (38 | 48) => {
+                // Either `38:5:n`, `38:2:r:g:b` and `38:2::r:g:b`, or the
+                // same with semicolons, spanning the following groups.
+                let (color, consumed) = if group.len() > 1 {
+                    (extended_color(&group[1..]), 0)
+                } else {
+                    let args = groups[i..].iter().map(|g| g[0]).collect::<Vec<_>>();
+                    let consumed = match args.first() {
+                        Some(5) => 2,
+                        Some(2) => 4,
+                        _ => 0,
+                    };
+                    (extended_color(&args[..consumed.min(args.len())]), consumed)
+                };
+                i += consumed;
+
+                if let Some(color) = color {
+                    if n == 38 {
+                        style.foreground = color;
+                    } else {
+                        style.background = color;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    style
+}
+
+const BASIC: [Color; 8] = [
+    Color::Black,
+    Color::Red,
+    Color::Green,
+    Color::Yellow,
+    Color::Blue,
+    Color::Magenta,
+    Color::Cyan,
+    Color::White,
+];
+
+#[must_use]
+fn extended_color(args: &[u16]) -> Option<Color> {
+    let byte = |n: &u16| u8::try_from(*n).ok();
+    match args {
+        [5, n] => byte(n).map(indexed_color),
+        [2, .., r, g, b] if args.len() <= 5 => Some(Color::Rgb(byte(r)?, byte(g)?, byte(b)?)),
+        _ => None,
+    }
+}
+
+// From: (https://en.wikipedia.org/wiki/ANSI_escape_code#8-bit)
+#[must_use]
+fn indexed_color(n: u8) -> Color {
+    match n {
+        0..=15 => BASIC[usize::from(n % 8)],
+        16..=231 => {
+            let level = |c: u8| if c == 0 { 0 } else { 55 + c * 40 };
+            let n = n - 16;
+            Color::Rgb(level(n / 36), level(n / 6 % 6), level(n % 6))
+        }
+        232..=255 => {
+            let level = 8 + (n - 232) * 10;
+            Color::Rgb(level, level, level)
+        }
+    }
+}
+
+#[must_use]
+fn number(digits: &[u8]) -> u16 {
+    digits.iter().fold(0u16, |n, d| {
+        n.saturating_mul(10).saturating_add(u16::from(d - b'0'))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::printer::Styled;
+
+    #[test]
+    fn sequences() {
+        assert_eq!(sequence_len(b"\x1B[1;31mx"), Some(7));
+        assert_eq!(sequence_len(b"\x1B[?25h"), Some(6));
+        assert_eq!(sequence_len(b"\x1B[1;3"), None);
+        assert_eq!(sequence_len(b"\x1B[1\nx"), Some(3));
+        assert_eq!(sequence_len(b"\x1B]0;title\x07x"), Some(10));
+        assert_eq!(sequence_len(b"\x1B]8;;http://a\x1B\\x"), Some(15));
+        assert_eq!(sequence_len(b"\x1B_Gi=1;OK\x1B\\"), Some(11));
+        assert_eq!(sequence_len(b"\x1BPq#0\x1B"), None);
+        assert_eq!(sequence_len(b"\x1B(B"), Some(3));
+        assert_eq!(sequence_len(b"\x1B7"), Some(2));
+        assert_eq!(sequence_len("\x1Bé".as_bytes()), Some(1));
+        assert_eq!(sequence_len(b"\x1B"), None);
+    }
+
+    #[test]
+    fn stripping() {
+        assert!(matches!(strip_ansi("plain"), Cow::Borrowed("plain")));
+
+        let styled = "漢字".with_foreground(Color::Rgb(1, 2, 3)).to_string();
+        assert_eq!(strip_ansi(&styled), "漢字");
+        assert_eq!(visible_width(&styled), 4);
+
+        let s = "\x1B]8;;http://a\x1B\\link\x1B]8;;\x07 \x1BPq#0;2;0;0;0\x1B\\\x1B_Ga=d\x1B\\\x1B[2J\x1B7é\x1B[1";
+        assert_eq!(strip_ansi(s), "link é");
+        assert_eq!(visible_width(s), 6);
+
+        assert_eq!(strip_ansi("\x1B[1m50%\r100%\tx"), "50%\r100%\tx");
+        assert_eq!(visible_width("\x1B[1m50%\r100%\tx"), 9);
+        assert_eq!(visible_width("abc\r\nde\x07"), 3);
+        assert_eq!(visible_width("漢字"), 4);
+    }
+
+    #[test]
+    fn styles() {
+        let text = parse(
+            b"\x1B[1;31merror\x1B[0m: a\x1B[38;5;208m\x1B[48:2::0:0:255mb\nc\x1B[39;22m\x1B[Kd",
+        );
+        let orange = Color::Rgb(255, 135, 0);
+
+        assert_eq!(
+            text.lines,
+            [
+                Line::from_iter([
+                    "error".with_style(Style::foreground(Color::Red).with_weight(FontWeight::Bold)),
+                    ": a".with_style(Style::new()),
+                    "b".with_style(
+                        Style::foreground(orange).with_background(Color::Rgb(0, 0, 255))
+                    ),
+                ]),
+                Line::from_iter([
+                    "c".with_style(
+                        Style::foreground(orange).with_background(Color::Rgb(0, 0, 255))
+                    ),
+                    "d".with_background(Color::Rgb(0, 0, 255)),
+                ]),
+            ]
+        );
+    }
+
+    #[test]
+    fn controls() {
+        let text = parse(b"50%\r100%\r\n\x1B[1ma\tb\tc\x07\x08\r\nab\r");
+        assert_eq!(
+            text.lines,
+            [
+                Line::from("100%"),
+                Line::from_iter(["a       b       c".with_weight(FontWeight::Bold),]),
+                Line::from("ab".with_weight(FontWeight::Bold)),
+            ]
+        );
+
+        let text = parse("漢字\tx\r\x1B[31my\tz".as_bytes());
+        assert_eq!(
+            text.lines,
+            [Line::from("y       z".with_foreground(Color::Red))]
+        );
+        assert_eq!(parse("漢字\tx".as_bytes()).width(), 9);
+    }
+
+    #[test]
+    fn streaming() {
+        let mut sut = Parser::new();
+        assert_eq!(
+            sut.feed(b"a\x1B[3"),
+            [Event::Text("a".to_string().with_style(Style::new()))]
+        );
+        assert_eq!(sut.feed(b"2m\xE2\x9C"), []);
+        assert_eq!(sut.style(), Style::foreground(Color::Green));
+        assert_eq!(
+            sut.feed(b"\x93\r\n\x1B]0;t\x07"),
+            [
+                Event::Text("\u{2713}".to_string().with_foreground(Color::Green)),
+                Event::Control(b"\r".to_vec()),
+                Event::Newline,
+                Event::Control(b"\x1B]0;t\x07".to_vec()),
+            ]
+        );
+        assert_eq!(sut.feed(b"\x1B]0;unterminated"), []);
+        assert_eq!(
+            sut.finish(),
+            [Event::Control(b"\x1B]0;unterminated".to_vec())]
+        );
+    }
+}
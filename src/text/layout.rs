@@ -0,0 +1,317 @@
+use crate::printer::{Style, StyledContent};
+use crate::text::{self, Width};
+
+const ELLIPSIS: &str = "…";
+
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Wrap {
+    // Breaks lines between words, words longer than a line overflow it.
+    #[default]
+    Word,
+    // Breaks lines between characters, regardless of words.
+    Char,
+    // Breaks lines between words, words longer than a line are broken
+    // between characters.
+    Anywhere,
+}
+
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Truncate {
+    Start,
+    Middle,
+    #[default]
+    End,
+}
+
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Align {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+type Line = Vec<StyledContent<String>>;
+
+// A grapheme along with the style of the span it belongs to.
+#[derive(Debug, Copy, Clone)]
+struct Cell<'a> {
+    grapheme: &'a str,
+    style: Style,
+    width: usize,
+}
+
+impl<'a> Cell<'a> {
+    fn is_newline(&self) -> bool {
+        matches!(self.grapheme, "\n" | "\r\n")
+    }
+
+    fn is_whitespace(&self) -> bool {
+        self.grapheme.chars().all(char::is_whitespace)
+    }
+}
+
+// Explicit newlines within the spans are always honored.
+pub fn wrap<T: AsRef<str>>(spans: &[StyledContent<T>], width: usize, wrap: Wrap) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut cells = cells(spans).peekable();
+
+    while cells.peek().is_some() {
+        let paragraph = cells
+            .by_ref()
+            .take_while(|c| !c.is_newline())
+            .collect::<Vec<_>>();
+
+        match wrap {
+            Wrap::Char => wrap_chars(&paragraph, width, &mut lines),
+            Wrap::Word | Wrap::Anywhere => wrap_words(&paragraph, width, wrap, &mut lines),
+        }
+    }
+
+    lines.into_iter().map(|line| join(&line)).collect()
+}
+
+fn wrap_chars<'a>(cells: &[Cell<'a>], width: usize, lines: &mut Vec<Vec<Cell<'a>>>) {
+    let mut line = Vec::new();
+    let mut used = 0;
+
+    for cell in cells {
+        if used + cell.width > width && !line.is_empty() {
+            lines.push(std::mem::take(&mut line));
+            used = 0;
+        }
+        used += cell.width;
+        line.push(*cell);
+    }
+
+    lines.push(line);
+}
+
+fn wrap_words<'a>(cells: &[Cell<'a>], width: usize, wrap: Wrap, lines: &mut Vec<Vec<Cell<'a>>>) {
+    let mut line = Vec::new();
+    let mut used = 0;
+
+    // Whitespace is kept between words of the same line and dropped
+    // where lines are broken.
+    let words = cells.chunk_by(|a, b| a.is_whitespace() == b.is_whitespace());
+    let mut pending: &[Cell] = &[];
+
+    for word in words {
+        if word[0].is_whitespace() {
+            pending = word;
+            continue;
+        }
+
+        let spacing = width_of(pending);
+        let len = width_of(word);
+
+        if used + spacing + len <= width {
+            line.extend_from_slice(pending);
+            line.extend_from_slice(word);
+            used += spacing + len;
+        } else {
+            if !line.is_empty() {
+                lines.push(std::mem::take(&mut line));
+            }
+
+            if len <= width || wrap == Wrap::Word {
+                line.extend_from_slice(word);
+                used = len;
+            } else {
+                let mut pieces = Vec::new();
+                wrap_chars(word, width, &mut pieces);
+                used = pieces.last().map_or(0, |p| width_of(p));
+                line = pieces.pop().unwrap_or_default();
+                lines.append(&mut pieces);
+            }
+        }
+
+        pending = &[];
+    }
+
+    if used + width_of(pending) <= width {
+        line.extend_from_slice(pending);
+    }
+
+    lines.push(line);
+}
+
+// Replaces the overflowing content with an ellipsis, which takes the
+// style of the first character it replaces.
+pub fn truncate<T: AsRef<str>>(spans: &[StyledContent<T>], width: usize, at: Truncate) -> Line {
+    let cells = cells(spans).collect::<Vec<_>>();
+
+    if width_of(&cells) <= width {
+        return join(&cells);
+    }
+
+    if width == 0 {
+        return Vec::new();
+    }
+
+    let budget = width - ELLIPSIS.width();
+    let (head, tail) = match at {
+        Truncate::Start => (0, budget),
+        Truncate::Middle => (budget - budget / 2, budget / 2),
+        Truncate::End => (budget, 0),
+    };
+
+    let head = take_width(cells.iter(), head);
+    let tail = take_width(cells.iter().rev(), tail);
+    let ellipsis = Cell {
+        grapheme: ELLIPSIS,
+        style: cells[head].style,
+        width: ELLIPSIS.width(),
+    };
+
+    let mut out = cells[..head].to_vec();
+    out.push(ellipsis);
+    out.extend_from_slice(&cells[cells.len() - tail..]);
+    join(&out)
+}
+
+// Pads the content with unstyled spaces, content wider than `width`
+// is left as is.
+pub fn align<T: AsRef<str>>(spans: &[StyledContent<T>], width: usize, align: Align) -> Line {
+    let cells = cells(spans).collect::<Vec<_>>();
+    let padding = width.saturating_sub(width_of(&cells));
+
+    let (left, right) = match align {
+        Align::Left => (0, padding),
+        Align::Center => (padding / 2, padding - padding / 2),
+        Align::Right => (padding, 0),
+    };
+
+    let pad = |n: usize| {
+        (n > 0).then(|| StyledContent {
+            style: Style::default(),
+            content: " ".repeat(n),
+        })
+    };
+
+    pad(left)
+        .into_iter()
+        .chain(join(&cells))
+        .chain(pad(right))
+        .collect()
+}
+
+fn cells<T: AsRef<str>>(spans: &[StyledContent<T>]) -> impl Iterator<Item = Cell<'_>> {
+    spans.iter().flat_map(|span| {
+        text::graphemes(span.content.as_ref()).map(|grapheme| Cell {
+            grapheme,
+            style: span.style,
+            width: text::grapheme_width(grapheme),
+        })
+    })
+}
+
+// Number of cells, taken from the iterator, fitting in `width` columns.
+fn take_width<'a>(cells: impl Iterator<Item = &'a Cell<'a>>, width: usize) -> usize {
+    cells
+        .scan(0, |used, cell| {
+            *used += cell.width;
+            Some(*used).filter(|used| *used <= width)
+        })
+        .count()
+}
+
+fn width_of(cells: &[Cell]) -> usize {
+    cells.iter().map(|c| c.width).sum()
+}
+
+// Merges consecutive cells sharing the same style into spans.
+fn join(cells: &[Cell]) -> Line {
+    cells
+        .chunk_by(|a, b| a.style == b.style)
+        .map(|run| StyledContent {
+            style: run[0].style,
+            content: run.iter().map(|c| c.grapheme).collect(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::printer::{Color, Styled};
+
+    fn span(content: &str) -> StyledContent<&str> {
+        content.with_style(Style::default())
+    }
+
+    fn plain(line: &Line) -> String {
+        line.iter().map(|span| span.content.as_str()).collect()
+    }
+
+    fn plains(lines: &[Line]) -> Vec<String> {
+        lines.iter().map(plain).collect()
+    }
+
+    #[test]
+    fn wrapping() {
+        let spans = [
+            "the quick ".with_foreground(Color::Red),
+            span("brown fox\njumps"),
+        ];
+
+        assert_eq!(
+            plains(&wrap(&spans, 10, Wrap::Word)),
+            ["the quick", "brown fox", "jumps"]
+        );
+        assert_eq!(
+            plains(&wrap(&spans, 7, Wrap::Char)),
+            ["the qui", "ck brow", "n fox", "jumps"]
+        );
+        assert_eq!(
+            plains(&wrap(&[span("a verylongword b")], 5, Wrap::Word)),
+            ["a", "verylongword", "b"]
+        );
+        assert_eq!(
+            plains(&wrap(&[span("a verylongword b")], 5, Wrap::Anywhere)),
+            ["a", "veryl", "ongwo", "rd b"]
+        );
+        assert_eq!(
+            plains(&wrap(&[span("漢字漢字")], 5, Wrap::Char)),
+            ["漢字", "漢字"]
+        );
+
+        let lines = wrap(&spans, 10, Wrap::Word);
+        assert_eq!(
+            lines[0],
+            ["the quick".to_string().with_foreground(Color::Red)]
+        );
+        assert_eq!(
+            lines[1],
+            ["brown fox".to_string().with_style(Style::default())]
+        );
+    }
+
+    #[test]
+    fn truncation() {
+        let spans = ["Hello".with_foreground(Color::Red), span(" world")];
+
+        assert_eq!(plain(&truncate(&spans, 11, Truncate::End)), "Hello world");
+        assert_eq!(plain(&truncate(&spans, 8, Truncate::End)), "Hello w…");
+        assert_eq!(plain(&truncate(&spans, 8, Truncate::Start)), "…o world");
+        assert_eq!(plain(&truncate(&spans, 8, Truncate::Middle)), "Hell…rld");
+        assert_eq!(plain(&truncate(&spans, 0, Truncate::End)), "");
+        assert_eq!(
+            plain(&truncate(&[span("漢字漢字")], 6, Truncate::End)),
+            "漢字…"
+        );
+
+        let line = truncate(&spans, 4, Truncate::End);
+        assert_eq!(line, ["Hel…".to_string().with_foreground(Color::Red)]);
+    }
+
+    #[test]
+    fn alignment() {
+        let spans = ["漢字".with_foreground(Color::Red)];
+
+        assert_eq!(plain(&align(&spans, 7, Align::Left)), "漢字   ");
+        assert_eq!(plain(&align(&spans, 7, Align::Center)), " 漢字  ");
+        assert_eq!(plain(&align(&spans, 7, Align::Right)), "   漢字");
+        assert_eq!(plain(&align(&spans, 2, Align::Right)), "漢字");
+    }
+}
@@ -0,0 +1,390 @@
+use crate::printer::{Color, FontWeight, Style, Styled, TextDecoration};
+use crate::text::{Line, Text};
+
+use std::borrow::Cow;
+use std::fmt::{self, Display, Formatter};
+use std::io;
+
+// Tags style the text up to the matching closing tag, e.g.
+// `[bold red on blue]text[/bold red on blue]`, or up to the first `[/]`.
+// Nested tags patch the style of the enclosing ones, tags left open
+// extend to the end of the text, `\[` and `\\` escape the brackets.
+//
+// Attributes are `bold` (`b`), `light` (`dim`), `underline` (`u`),
+// `strike` (`s`), color names or `#rrggbb` for the foreground, and
+// `on` followed by a color for the background.
+pub fn parse(markup: &str) -> io::Result<Text<'static>> {
+    validate(markup).map_err(make_err)?;
+
+    let bytes = markup.as_bytes();
+    let mut text = Text::new();
+    let mut line = Line::new();
+    let mut run = String::new();
+    let mut styles = vec![Style::default()];
+    let mut start = 0;
+    let mut i = 0;
+
+    // Moves the pending text to the line, in the innermost style.
+    let flush = |line: &mut Line, run: &mut String, styles: &[Style]| {
+        if !run.is_empty() {
+            let style = styles.last().copied().unwrap_or_default();
+            line.push(std::mem::take(run).with_style(style));
+        }
+    };
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' if matches!(bytes.get(i + 1), Some(b'[' | b'\\')) => {
+                run.push_str(&markup[start..i]);
+                start = i + 1;
+                i += 2;
+            }
+            b'\n' => {
+                run.push_str(&markup[start..i]);
+                flush(&mut line, &mut run, &styles);
+                text.push(std::mem::take(&mut line));
+                start = i + 1;
+                i += 1;
+            }
+            b'[' => {
+                let end = i + markup[i..].find(']').unwrap_or(markup.len() - i);
+                run.push_str(&markup[start..i]);
+                flush(&mut line, &mut run, &styles);
+
+                if bytes[i + 1] == b'/' {
+                    styles.pop();
+                } else {
+                    let style = tag(bytes, i + 1, end).map_err(make_err)?;
+                    let outer = styles.last().copied().unwrap_or_default();
+                    styles.push(outer.patch(style));
+                }
+                start = end + 1;
+                i = end + 1;
+            }
+            _ => i += 1,
+        }
+    }
+
+    run.push_str(&markup[start..]);
+    flush(&mut line, &mut run, &styles);
+    text.push(line);
+    Ok(text)
+}
+
+// Escapes the brackets so that `s` is taken literally once parsed.
+#[must_use]
+pub fn escape(s: &str) -> Cow<'_, str> {
+    if s.contains(['[', '\\']) {
+        s.replace('\\', "\\\\").replace('[', "\\[").into()
+    } else {
+        s.into()
+    }
+}
+
+// Displays the escaped content, used by `markup!` for its arguments.
+pub struct Escaped<T>(pub T);
+
+impl<T: Display> Display for Escaped<T> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(&escape(&self.0.to_string()))
+    }
+}
+
+#[doc(hidden)]
+pub use termular_macros::format_escaped;
+
+// Formats the arguments, escaping them, and parses the result, the format
+// string is validated at compile time. Arguments captured inline in the
+// format string (e.g. `{path}`) are escaped too.
+#[macro_export]
+macro_rules! markup {
+    ($fmt:literal $(, $arg:expr)* $(,)?) => {{
+        const _: () = if let Err(e) = $crate::text::markup::validate($fmt) {
+            panic!("{}", e)
+        };
+        $crate::text::markup::parse(&$crate::text::markup::format_escaped!(
+            $crate::text::markup::Escaped;
+            $fmt $(, $crate::text::markup::Escaped(&$arg))*
+        ))
+    }};
+}
+
+const MAX_DEPTH: usize = 32;
+
+// Checks the syntax of the tags and their attributes, usable in const contexts.
+pub const fn validate(markup: &str) -> Result<(), &'static str> {
+    let bytes = markup.as_bytes();
+    let mut open = [(0, 0); MAX_DEPTH];
+    let mut depth = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' if i + 1 < bytes.len() && matches!(bytes[i + 1], b'[' | b'\\') => i += 2,
+            b'[' => {
+                let mut end = i + 1;
+                while end < bytes.len() && bytes[end] != b']' {
+                    end += 1;
+                }
+                if end == bytes.len() {
+                    return Err("unterminated markup tag");
+                }
+
+                if bytes[i + 1] == b'/' {
+                    if depth == 0 {
+                        return Err("markup closing tag without an opening tag");
+                    }
+                    let (start, stop) = open[depth - 1];
+                    if end != i + 2 && !eq(slice(bytes, start, stop), slice(bytes, i + 2, end)) {
+                        return Err("markup closing tag doesn't match the opening tag");
+                    }
+                    depth -= 1;
+                } else {
+                    if let Err(e) = tag(bytes, i + 1, end) {
+                        return Err(e);
+                    }
+                    if depth == MAX_DEPTH {
+                        return Err("markup tags nested too deeply");
+                    }
+                    open[depth] = (i + 1, end);
+                    depth += 1;
+                }
+                i = end + 1;
+            }
+            _ => i += 1,
+        }
+    }
+
+    Ok(())
+}
+
+// Parses the attributes of the tag in `bytes[start..end]`.
+const fn tag(bytes: &[u8], start: usize, end: usize) -> Result<Style, &'static str> {
+    let mut style = Style {
+        foreground: Color::Default,
+        background: Color::Default,
+        decoration: TextDecoration::None,
+        weight: FontWeight::Normal,
+    };
+    let mut background = false;
+    let mut empty = true;
+    let mut i = start;
+
+    while i < end {
+        if bytes[i] == b' ' {
+            i += 1;
+            continue;
+        }
+
+        let mut j = i;
+        while j < end && bytes[j] != b' ' {
+            j += 1;
+        }
+
+        let word = slice(bytes, i, j);
+        if background {
+            match color(word) {
+                Some(color) => style.background = color,
+                None => return Err("expected a color after `on` in markup tag"),
+            }
+            background = false;
+        } else if eq(word, b"on") {
+            background = true;
+        } else if let Some(color) = color(word) {
+            style.foreground = color;
+        } else if eq(word, b"bold") || eq(word, b"b") {
+            style.weight = FontWeight::Bold;
+        } else if eq(word, b"light") || eq(word, b"dim") {
+            style.weight = FontWeight::Light;
+        } else if eq(word, b"underline") || eq(word, b"u") {
+            style.decoration = TextDecoration::Underline;
+        } else if eq(word, b"strike") || eq(word, b"s") {
+            style.decoration = TextDecoration::Strike;
+        } else {
+            return Err("unknown markup attribute");
+        }
+
+        empty = false;
+        i = j;
+    }
+
+    if background {
+        Err("expected a color after `on` in markup tag")
+    } else if empty {
+        Err("empty markup tag")
+    } else {
+        Ok(style)
+    }
+}
+
+const COLORS: [(&[u8], Color); 9] = [
+    (b"default", Color::Default),
+    (b"black", Color::Black),
+    (b"red", Color::Red),
+    (b"green", Color::Green),
+    (b"yellow", Color::Yellow),
+    (b"blue", Color::Blue),
+    (b"magenta", Color::Magenta),
+    (b"cyan", Color::Cyan),
+    (b"white", Color::White),
+];
+
+const fn color(word: &[u8]) -> Option<Color> {
+    if let [b'#', hex @ ..] = word {
+        if hex.len() != 6 {
+            return None;
+        }
+        return match (hex_byte(hex, 0), hex_byte(hex, 2), hex_byte(hex, 4)) {
+            (Some(r), Some(g), Some(b)) => Some(Color::Rgb(r, g, b)),
+            _ => None,
+        };
+    }
+
+    let mut i = 0;
+    while i < COLORS.len() {
+        if eq(word, COLORS[i].0) {
+            return Some(COLORS[i].1);
+        }
+        i += 1;
+    }
+    None
+}
+
+const fn hex_byte(hex: &[u8], i: usize) -> Option<u8> {
+    match (hex_digit(hex[i]), hex_digit(hex[i + 1])) {
+        (Some(high), Some(low)) => Some(high << 4 | low),
+        _ => None,
+    }
+}
+
+const fn hex_digit(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        b'A'..=b'F' => Some(c - b'A' + 10),
+        _ => None,
+    }
+}
+
+const fn slice(bytes: &[u8], start: usize, end: usize) -> &[u8] {
+    bytes.split_at(end).0.split_at(start).1
+}
+
+const fn eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+fn make_err(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::text::Span;
+
+    #[test]
+    fn parsing() {
+        let text = parse("[bold red]Error:[/] file [u]a.rs[/u]").unwrap();
+        assert_eq!(
+            text.lines,
+            [Line::from_iter([
+                "Error:".with_style(Style::foreground(Color::Red).with_weight(FontWeight::Bold)),
+                " file ".with_style(Style::new()),
+                "a.rs".with_decoration(TextDecoration::Underline),
+            ])]
+        );
+
+        let text = parse("[on #ff8000]a[b]b\nc[/b]d").unwrap();
+        let orange = Style::background(Color::Rgb(0xFF, 0x80, 0));
+        assert_eq!(
+            text.lines,
+            [
+                Line::from_iter([
+                    "a".with_style(orange),
+                    "b".with_style(orange.with_weight(FontWeight::Bold)),
+                ]),
+                Line::from_iter([
+                    "c".with_style(orange.with_weight(FontWeight::Bold)),
+                    "d".with_style(orange),
+                ]),
+            ]
+        );
+
+        let text = parse(r"\[red] \\[red]x").unwrap();
+        assert_eq!(
+            text.lines[0].spans,
+            [
+                Span::from("[red] \\"),
+                Span::from("x".with_foreground(Color::Red))
+            ]
+        );
+    }
+
+    #[test]
+    fn validation() {
+        assert!(validate("[b]a[/b] [red on blue]b[/] c]").is_ok());
+        assert!(validate("[b]a").is_ok());
+        assert!(validate("[b").is_err());
+        assert!(validate("[]").is_err());
+        assert!(validate("[/]").is_err());
+        assert!(validate("[b]a[/u]").is_err());
+        assert!(validate("[bolder]").is_err());
+        assert!(validate("[red on]").is_err());
+        assert!(validate("[#12345g]").is_err());
+        assert!(parse("[b").is_err());
+
+        assert!(validate("{{path}} [b]{}[/b] {0:?} {:>4}").is_ok());
+        assert!(validate("[b]{}").is_ok());
+        assert!(validate("[b{}]").is_err());
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!(escape("plain"), "plain");
+        assert_eq!(escape(r"[x]\"), r"\[x]\\");
+
+        let path = "[b]a.rs";
+        let text = crate::markup!("[u]{}[/u] {}", path, 1).unwrap();
+        assert_eq!(
+            text.lines[0].spans,
+            [
+                Span::from("[b]a.rs".with_decoration(TextDecoration::Underline)),
+                Span::from(" 1"),
+            ]
+        );
+
+        let path = "src/[id].rs";
+        let text = crate::markup!("[b]{0}[/b] {0}", path).unwrap();
+        assert_eq!(
+            text.lines[0].spans,
+            [
+                Span::from("src/[id].rs".with_weight(FontWeight::Bold)),
+                Span::from(" src/[id].rs"),
+            ]
+        );
+
+        let (count, width) = (2, 4);
+        let text = crate::markup!("[u]{path}[/u] {{path}} {count:>width$}{}", '\\').unwrap();
+        assert_eq!(
+            text.lines[0].spans,
+            [
+                Span::from("src/[id].rs".with_decoration(TextDecoration::Underline)),
+                Span::from(" {path} 2\\"),
+            ]
+        );
+
+        let text = crate::markup!("\u{e9}{path}").unwrap();
+        assert_eq!(text.lines[0].spans, [Span::from("\u{e9}src/[id].rs")]);
+    }
+}
@@ -0,0 +1,332 @@
+use crate::printer::{Style, Styled, StyledContent};
+use crate::text::layout::{self, Align, Truncate, Wrap};
+use crate::text::Width;
+
+use std::borrow::Cow;
+use std::fmt::{self, Display, Formatter};
+use std::ops::{Add, AddAssign};
+
+pub type Span<'a> = StyledContent<Cow<'a, str>>;
+
+impl Styled for Cow<'_, str> {}
+
+impl<'a> From<&'a str> for Span<'a> {
+    fn from(content: &'a str) -> Self {
+        Cow::Borrowed(content).with_style(Style::default())
+    }
+}
+
+impl From<String> for Span<'_> {
+    fn from(content: String) -> Self {
+        Cow::<str>::Owned(content).with_style(Style::default())
+    }
+}
+
+impl<'a> From<StyledContent<&'a str>> for Span<'a> {
+    fn from(StyledContent { style, content }: StyledContent<&'a str>) -> Self {
+        Cow::Borrowed(content).with_style(style)
+    }
+}
+
+impl From<StyledContent<String>> for Span<'_> {
+    fn from(StyledContent { style, content }: StyledContent<String>) -> Self {
+        Cow::<str>::Owned(content).with_style(style)
+    }
+}
+
+// A sequence of spans on a single row, the line style is the base style
+// patched by the style of each span.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Line<'a> {
+    pub style: Style,
+    pub spans: Vec<Span<'a>>,
+}
+
+impl<'a> Line<'a> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn with_style(mut self, style: Style) -> Self {
+        self.style = style;
+        self
+    }
+
+    pub fn push(&mut self, span: impl Into<Span<'a>>) {
+        self.spans.push(span.into());
+    }
+
+    // Spans with the line style applied.
+    pub fn styled_spans(&self) -> impl Iterator<Item = StyledContent<&str>> {
+        self.spans.iter().map(|span| StyledContent {
+            style: self.style.patch(span.style),
+            content: span.content.as_ref(),
+        })
+    }
+
+    #[must_use]
+    pub fn wrap(&self, width: usize, wrap: Wrap) -> Vec<Line<'static>> {
+        layout::wrap(&self.spans, width, wrap)
+            .into_iter()
+            .map(|spans| self.relayout(spans))
+            .collect()
+    }
+
+    #[must_use]
+    pub fn truncate(&self, width: usize, at: Truncate) -> Line<'static> {
+        self.relayout(layout::truncate(&self.spans, width, at))
+    }
+
+    #[must_use]
+    pub fn align(&self, width: usize, align: Align) -> Line<'static> {
+        self.relayout(layout::align(&self.spans, width, align))
+    }
+
+    #[must_use]
+    fn relayout(&self, spans: Vec<StyledContent<String>>) -> Line<'static> {
+        Line {
+            style: self.style,
+            spans: spans.into_iter().map(Span::from).collect(),
+        }
+    }
+}
+
+impl Width for Line<'_> {
+    fn width(&self) -> usize {
+        self.spans.iter().map(|span| span.content.width()).sum()
+    }
+}
+
+impl Display for Line<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        self.styled_spans()
+            .try_for_each(|span| write!(f, "{}", span))
+    }
+}
+
+impl<'a, S: Into<Span<'a>>> From<S> for Line<'a> {
+    fn from(span: S) -> Self {
+        Self {
+            style: Style::default(),
+            spans: vec![span.into()],
+        }
+    }
+}
+
+impl<'a, S: Into<Span<'a>>> FromIterator<S> for Line<'a> {
+    fn from_iter<I: IntoIterator<Item = S>>(spans: I) -> Self {
+        Self {
+            style: Style::default(),
+            spans: spans.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl<'a, S: Into<Span<'a>>> Extend<S> for Line<'a> {
+    fn extend<I: IntoIterator<Item = S>>(&mut self, spans: I) {
+        self.spans.extend(spans.into_iter().map(Into::into));
+    }
+}
+
+impl<'a, S: Into<Span<'a>>> Add<S> for Line<'a> {
+    type Output = Self;
+
+    fn add(mut self, span: S) -> Self::Output {
+        self += span;
+        self
+    }
+}
+
+impl<'a, S: Into<Span<'a>>> AddAssign<S> for Line<'a> {
+    fn add_assign(&mut self, span: S) {
+        self.push(span);
+    }
+}
+
+// A sequence of lines, the text style is the base style of every line.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Text<'a> {
+    pub style: Style,
+    pub lines: Vec<Line<'a>>,
+}
+
+impl<'a> Text<'a> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn with_style(mut self, style: Style) -> Self {
+        self.style = style;
+        self
+    }
+
+    pub fn push(&mut self, line: impl Into<Line<'a>>) {
+        self.lines.push(line.into());
+    }
+
+    // Lines with the text style applied.
+    pub fn styled_lines(&self) -> impl Iterator<Item = Line<'_>> {
+        self.lines.iter().map(|line| Line {
+            style: self.style.patch(line.style),
+            spans: line
+                .spans
+                .iter()
+                .map(|span| Cow::Borrowed(span.content.as_ref()).with_style(span.style))
+                .collect(),
+        })
+    }
+
+    #[must_use]
+    pub fn wrap(&self, width: usize, wrap: Wrap) -> Text<'static> {
+        Text {
+            style: self.style,
+            lines: self
+                .lines
+                .iter()
+                .flat_map(|l| l.wrap(width, wrap))
+                .collect(),
+        }
+    }
+}
+
+impl Width for Text<'_> {
+    fn width(&self) -> usize {
+        self.lines.iter().map(Width::width).max().unwrap_or(0)
+    }
+}
+
+// Lines are separated by `\r\n`, so that they start at the first column
+// even when output post-processing is disabled (e.g. in raw mode).
+impl Display for Text<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        self.styled_lines().enumerate().try_for_each(|(i, line)| {
+            if i > 0 {
+                f.write_str("\r\n")?;
+            }
+            write!(f, "{}", line)
+        })
+    }
+}
+
+impl<'a> From<&'a str> for Text<'a> {
+    fn from(content: &'a str) -> Self {
+        content.lines().map(Line::from).collect()
+    }
+}
+
+impl From<String> for Text<'_> {
+    fn from(content: String) -> Self {
+        content.lines().map(|l| Line::from(l.to_string())).collect()
+    }
+}
+
+impl<'a> From<Line<'a>> for Text<'a> {
+    fn from(line: Line<'a>) -> Self {
+        Self {
+            style: Style::default(),
+            lines: vec![line],
+        }
+    }
+}
+
+impl<'a, L: Into<Line<'a>>> FromIterator<L> for Text<'a> {
+    fn from_iter<I: IntoIterator<Item = L>>(lines: I) -> Self {
+        Self {
+            style: Style::default(),
+            lines: lines.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl<'a, L: Into<Line<'a>>> Extend<L> for Text<'a> {
+    fn extend<I: IntoIterator<Item = L>>(&mut self, lines: I) {
+        self.lines.extend(lines.into_iter().map(Into::into));
+    }
+}
+
+impl<'a, L: Into<Line<'a>>> Add<L> for Text<'a> {
+    type Output = Self;
+
+    fn add(mut self, line: L) -> Self::Output {
+        self += line;
+        self
+    }
+}
+
+impl<'a, L: Into<Line<'a>>> AddAssign<L> for Text<'a> {
+    fn add_assign(&mut self, line: L) {
+        self.push(line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::printer::{Color, FontWeight};
+
+    #[test]
+    fn inheritance() {
+        let line = (Line::new() + "Error: ".with_foreground(Color::Red) + "file " + "a.rs")
+            .with_style(Style::from(FontWeight::Bold).with_background(Color::Blue));
+
+        assert_eq!(line.width(), 16);
+        assert_eq!(
+            line.styled_spans().collect::<Vec<_>>(),
+            [
+                "Error: ".with_style(
+                    Style::foreground(Color::Red)
+                        .with_background(Color::Blue)
+                        .with_weight(FontWeight::Bold)
+                ),
+                "file ".with_style(line.style),
+                "a.rs".with_style(line.style),
+            ]
+        );
+
+        let text = Text::from("one\ntwo").with_style(Style::foreground(Color::Green)) + line;
+        assert_eq!(text.lines.len(), 3);
+        assert_eq!(text.width(), 16);
+
+        let lines = text.styled_lines().collect::<Vec<_>>();
+        assert_eq!(lines[0].style, Style::foreground(Color::Green));
+        assert_eq!(lines[2].style.foreground, Color::Green);
+        assert_eq!(lines[2].style.background, Color::Blue);
+        assert_eq!(
+            text.to_string(),
+            format!(
+                "{}\r\n{}\r\n{}",
+                "one".with_foreground(Color::Green),
+                "two".with_foreground(Color::Green),
+                lines[2]
+            )
+        );
+    }
+
+    #[test]
+    fn layout() {
+        let line = Line::from_iter([
+            "ab ".with_foreground(Color::Red),
+            "cd".with_style(Style::new()),
+        ]);
+
+        let lines = line.wrap(2, Wrap::Word);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[0].to_string(),
+            "ab".with_foreground(Color::Red).to_string()
+        );
+
+        let line = line
+            .with_style(Style::background(Color::Blue))
+            .align(7, Align::Right);
+        assert_eq!(line.width(), 7);
+        assert_eq!(
+            line.styled_spans().next().unwrap().style,
+            Style::background(Color::Blue)
+        );
+    }
+}